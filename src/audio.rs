@@ -1,4 +1,5 @@
 use anyhow::Result;
+use hashbrown::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
@@ -10,10 +11,22 @@ use cpal::{FromSample, Sample, SizedSample};
 // const RB_SIZE: usize = 200;
 const BLOCK_SIZE: usize = 128;
 
+/// The code to run on each engine, keyed by the buffer that owns it; `None`
+/// is the engine shared by every buffer without one of its own. Empty code
+/// for a buffer's engine stops it.
+pub type EngineCodes = Arc<Mutex<HashMap<Option<usize>, String>>>;
+
+/// The last error an engine reported, and which engine.
+pub type EngineError = Arc<Mutex<Option<(Option<usize>, String)>>>;
+
+/// The code to run, whether it changed, where the engines report errors, and
+/// whether the output is muted by a panic.
+pub type AudioOptions = (EngineCodes, Arc<AtomicBool>, EngineError, Arc<AtomicBool>);
+
 pub fn run_audio<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    options: (Arc<Mutex<String>>, Arc<AtomicBool>),
+    options: AudioOptions,
     // options: (
     //     Arc<AtomicPtr<f32>>,
     //     Arc<AtomicPtr<f32>>,
@@ -32,22 +45,29 @@ where
     tracing::warn!("run_audio");
     let sr = config.sample_rate.0 as usize;
     let bpm = 120.;
-    let mut engine = glicol::Engine::<BLOCK_SIZE>::new();
+    let mut engines = HashMap::new();
     // let mut code = String::from("o: sin 220");
     // let ptr = unsafe { code.as_bytes_mut().as_mut_ptr() };
     // let code_ptr = Arc::new(AtomicPtr::<u8>::new(ptr));
     // let code_len = Arc::new(AtomicUsize::new(code.len()));
     // let has_update = Arc::new(AtomicBool::new(true));
     // let code = Arc::clone(&options.0);
-    let code_clone = Arc::clone(&options.0);
+    let codes = Arc::clone(&options.0);
     let has_update = Arc::clone(&options.1);
+    let engine_error = Arc::clone(&options.2);
+    let muted = Arc::clone(&options.3);
 
     // let _code_ptr = Arc::clone(&code_ptr);
     // let _code_len = Arc::clone(&code_len);
     // let _has_update = Arc::clone(&has_update);
     let channels = 2 as usize; //config.channels as usize;
-    engine.set_sr(sr);
-    engine.set_bpm(bpm);
+    let new_engine = move || {
+        let mut engine = glicol::Engine::<BLOCK_SIZE>::new();
+        engine.set_sr(sr);
+        engine.set_bpm(bpm);
+        engine
+    };
+    engines.insert(None, new_engine());
 
     let mut prev_block: [glicol_synth::Buffer<BLOCK_SIZE>; 2] = [glicol_synth::Buffer::SILENT; 2];
 
@@ -69,13 +89,24 @@ where
         move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
             // write_data(data, channels, &mut next_value);
             // tracing::warn!("data len: {}", data.len());
+            // after a panic the engine stands still until the next update
+            if muted.load(Ordering::Acquire) {
+                data.fill(T::EQUILIBRIUM);
+                return;
+            }
             if has_update.load(Ordering::Acquire) {
                 // let ptr = _code_ptr.load(Ordering::Acquire);
                 // let len = _code_len.load(Ordering::Acquire);
                 // let encoded: &[u8] = unsafe { std::slice::from_raw_parts(ptr, len) };
                 // let code = std::str::from_utf8(encoded.clone()).unwrap().to_owned();
-                let code = code_clone.lock().unwrap().as_str().to_owned();
-                engine.update_with_code(&code);
+                for (id, code) in codes.lock().unwrap().drain() {
+                    if id.is_some() && code.is_empty() {
+                        engines.remove(&id);
+                    } else {
+                        let engine = engines.entry(id).or_insert_with(new_engine);
+                        engine.update_with_code(&code);
+                    }
+                }
                 has_update.store(false, Ordering::Release);
             };
             let block_step = data.len() / channels;
@@ -100,7 +131,25 @@ where
 
             prev_block_pos = BLOCK_SIZE;
             while writes < block_step {
-                let (block, _err_msg) = engine.next_block(vec![]);
+                // the engines play side by side
+                let mut mix = [glicol_synth::Buffer::<BLOCK_SIZE>::SILENT; 2];
+                for (id, engine) in engines.iter_mut() {
+                    let (block, err_msg) = engine.next_block(vec![]);
+                    // the first byte is the error kind, the message starts at 2
+                    if err_msg[0] != 0 {
+                        let len = err_msg[2..].iter().position(|b| *b == 0).unwrap_or(254);
+                        let message = String::from_utf8_lossy(&err_msg[2..2 + len]).into_owned();
+                        if let Ok(mut error) = engine_error.try_lock() {
+                            *error = Some((*id, message));
+                        }
+                    }
+                    for (sum, buffer) in mix.iter_mut().zip(block) {
+                        for (sum, sample) in sum.iter_mut().zip(buffer.iter()) {
+                            *sum += sample;
+                        }
+                    }
+                }
+                let block = &mix;
                 if writes + BLOCK_SIZE <= block_step {
                     for i in 0..BLOCK_SIZE {
                         write_samples(block, writes, i);
@@ -0,0 +1,85 @@
+use crate::TextBuffer;
+
+const PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// The char typing `ch` closes automatically, for brackets and quotes.
+pub fn closing_pair(ch: char) -> Option<char> {
+    match ch {
+        '"' | '\'' => Some(ch),
+        _ => PAIRS
+            .iter()
+            .find(|(open, _)| *open == ch)
+            .map(|(_, close)| *close),
+    }
+}
+
+pub fn is_closing(ch: char) -> bool {
+    PAIRS.iter().any(|(_, close)| *close == ch)
+}
+
+/// Whether typing `ch` at `pos` should also insert its closing pair: only
+/// in front of whitespace or a closing bracket, and for quotes not right
+/// after a word so `don't` in a comment stays intact.
+pub fn should_pair(buffer: &TextBuffer, pos: usize, ch: char) -> bool {
+    let next_ok = match buffer.get_char(pos) {
+        Some(next) => next.is_whitespace() || is_closing(next),
+        None => true,
+    };
+    if ch == '"' || ch == '\'' {
+        let prev_ok = pos == 0 || !crate::is_word_char(buffer.char(pos - 1));
+        next_ok && prev_ok
+    } else {
+        next_ok
+    }
+}
+
+/// The bracket next to the cursor at `pos` and the one it matches, as char
+/// indices. The bracket after the cursor wins over the one before it.
+pub fn matching_bracket(buffer: &TextBuffer, pos: usize) -> Option<(usize, usize)> {
+    let candidates = [Some(pos), pos.checked_sub(1)];
+    for at in candidates.into_iter().flatten() {
+        let Some(ch) = buffer.get_char(at) else {
+            continue;
+        };
+        for (open, close) in PAIRS {
+            if ch == open {
+                return find_match(buffer, at, open, close, true).map(|m| (at, m));
+            } else if ch == close {
+                return find_match(buffer, at, close, open, false).map(|m| (at, m));
+            }
+        }
+    }
+    None
+}
+
+// walks away from the bracket at `from`, counting nested pairs of the same
+// kind, until `target` closes it
+fn find_match(
+    buffer: &TextBuffer,
+    from: usize,
+    this: char,
+    target: char,
+    forward: bool,
+) -> Option<usize> {
+    let mut depth = 0;
+    let mut pos = from;
+    loop {
+        if forward {
+            pos += 1;
+            if pos >= buffer.len_chars() {
+                return None;
+            }
+        } else {
+            pos = pos.checked_sub(1)?;
+        }
+        let ch = buffer.char(pos);
+        if ch == this {
+            depth += 1;
+        } else if ch == target {
+            if depth == 0 {
+                return Some(pos);
+            }
+            depth -= 1;
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::ops::Range;
+
+use ropey::{iter::Chars, Rope};
+
+/// The text being edited, stored in a rope so that inserts, deletes and
+/// line lookups stay O(log n) on long performance sets.
+///
+/// Only `\n` is treated as a line break, matching how the renderer lays out
+/// lines.
+#[derive(Clone, Default)]
+pub struct TextBuffer {
+    rope: Rope,
+    revision: u64,
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the edits, so anything worked out from the text can tell
+    /// when it is out of date.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_chars() == 0
+    }
+
+    /// Number of lines; a trailing `\n` starts a new, empty line.
+    pub fn len_lines(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub fn char(&self, idx: usize) -> char {
+        self.rope.char(idx)
+    }
+
+    pub fn get_char(&self, idx: usize) -> Option<char> {
+        self.rope.get_char(idx)
+    }
+
+    pub fn chars(&self) -> Chars<'_> {
+        self.rope.chars()
+    }
+
+    pub fn chars_at(&self, idx: usize) -> Chars<'_> {
+        self.rope.chars_at(idx)
+    }
+
+    pub fn insert_char(&mut self, idx: usize, ch: char) {
+        self.rope.insert_char(idx, ch);
+        self.revision += 1;
+    }
+
+    pub fn insert(&mut self, idx: usize, text: &str) {
+        self.rope.insert(idx, text);
+        self.revision += 1;
+    }
+
+    pub fn remove(&mut self, range: Range<usize>) {
+        self.rope.remove(range);
+        self.revision += 1;
+    }
+
+    /// The text in `range` as an owned string.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.slice(range).to_string()
+    }
+
+    pub fn char_to_line(&self, idx: usize) -> usize {
+        self.rope.char_to_line(idx)
+    }
+
+    pub fn line_to_char(&self, line: usize) -> usize {
+        self.rope.line_to_char(line)
+    }
+
+    /// Length of `line` in chars, not counting its line break.
+    pub fn line_len(&self, line: usize) -> usize {
+        let slice = self.rope.line(line);
+        let len = slice.len_chars();
+        if len > 0 && slice.char(len - 1) == '\n' {
+            len - 1
+        } else {
+            len
+        }
+    }
+
+    /// Char index of the end of `line`, i.e. the position of its line break
+    /// or the end of the text for the last line.
+    pub fn line_end(&self, line: usize) -> usize {
+        self.line_to_char(line) + self.line_len(line)
+    }
+
+    /// The text of `line` without its line break.
+    pub fn line(&self, line: usize) -> String {
+        let start = self.line_to_char(line);
+        self.slice(start..start + self.line_len(line))
+    }
+
+    pub fn char_to_line_col(&self, idx: usize) -> (usize, usize) {
+        let line = self.char_to_line(idx);
+        (line, idx - self.line_to_char(line))
+    }
+
+    /// Char index for `line` and `col`, clamping `col` to the line length
+    /// and `line` to the last line.
+    pub fn line_col_to_char(&self, line: usize, col: usize) -> usize {
+        let line = line.min(self.len_lines() - 1);
+        self.line_to_char(line) + col.min(self.line_len(line))
+    }
+
+    /// Char index of the char containing byte `idx` of the UTF-8 text.
+    pub fn byte_to_char(&self, idx: usize) -> usize {
+        self.rope.byte_to_char(idx)
+    }
+
+    /// Char index of the first occurrence of `needle` at or after `from`.
+    pub fn find(&self, needle: &str, from: usize) -> Option<usize> {
+        if needle.is_empty() {
+            return Some(from);
+        }
+        let byte_from = self.rope.char_to_byte(from);
+        let (chunks, chunk_start, _, _) = self.rope.chunks_at_byte(byte_from);
+        // a match can straddle two chunks, so the end of one chunk too short
+        // to hold the needle is searched again with the next
+        let mut window = String::new();
+        let mut window_start = byte_from;
+        for (i, chunk) in chunks.enumerate() {
+            window.push_str(if i == 0 {
+                &chunk[byte_from - chunk_start..]
+            } else {
+                chunk
+            });
+            if let Some(b) = window.find(needle) {
+                return Some(self.rope.byte_to_char(window_start + b));
+            }
+            let mut keep = window.len().saturating_sub(needle.len() - 1);
+            while !window.is_char_boundary(keep) {
+                keep += 1;
+            }
+            window.drain(..keep);
+            window_start += keep;
+        }
+        None
+    }
+
+    /// The range of the word touching `pos`, if any. A `~track` name counts
+    /// as one word together with its `~`.
+    pub fn word_at(&self, pos: usize) -> Option<Range<usize>> {
+        let start = self.word_start(pos);
+        let mut end = pos;
+        // right in front of a `~name`
+        if end == start && self.get_char(end) == Some('~') {
+            end += 1;
+        }
+        while end < self.len_chars() && is_word_char(self.char(end)) {
+            end += 1;
+        }
+        if (start..end).any(|i| is_word_char(self.char(i))) {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+
+    /// Where Ctrl+Left from `pos` lands: the start of the previous word or
+    /// of the run of punctuation before it, skipping whitespace.
+    pub fn prev_word_boundary(&self, pos: usize) -> usize {
+        let mut pos = pos;
+        while pos > 0 && self.char(pos - 1).is_whitespace() {
+            pos -= 1;
+        }
+        if pos > 0 && is_word_char(self.char(pos - 1)) {
+            return self.word_start(pos);
+        }
+        while pos > 0 && is_punctuation(self.char(pos - 1)) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// Where Ctrl+Right from `pos` lands: the end of the next word or run of
+    /// punctuation, skipping whitespace.
+    pub fn next_word_boundary(&self, pos: usize) -> usize {
+        let len = self.len_chars();
+        let mut pos = pos;
+        while pos < len && self.char(pos).is_whitespace() {
+            pos += 1;
+        }
+        let starts_name =
+            self.get_char(pos) == Some('~') && self.get_char(pos + 1).is_some_and(is_word_char);
+        if starts_name || (pos < len && is_word_char(self.char(pos))) {
+            if starts_name {
+                pos += 1;
+            }
+            while pos < len && is_word_char(self.char(pos)) {
+                pos += 1;
+            }
+            return pos;
+        }
+        while pos < len && is_punctuation(self.char(pos)) {
+            pos += 1;
+        }
+        pos
+    }
+
+    /// Char index of the first non-blank char on `line`, or its end when
+    /// the line is blank.
+    pub fn line_indent_end(&self, line: usize) -> usize {
+        let start = self.line_to_char(line);
+        let indent = self
+            .chars_at(start)
+            .take(self.line_len(line))
+            .take_while(|ch| ch.is_whitespace())
+            .count();
+        start + indent
+    }
+
+    // walks back over the word ending at `pos`, taking a leading `~` along
+    fn word_start(&self, pos: usize) -> usize {
+        let mut start = pos;
+        while start > 0 && is_word_char(self.char(start - 1)) {
+            start -= 1;
+        }
+        if start < pos && start > 0 && self.char(start - 1) == '~' {
+            start -= 1;
+        }
+        start
+    }
+}
+
+/// Word chars for double-click and Ctrl+arrow jumps. `_` is included so a
+/// seq pattern step like `_60` or `__` moves as one unit.
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+fn is_punctuation(ch: char) -> bool {
+    !ch.is_whitespace() && !is_word_char(ch)
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            revision: 0,
+        }
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_matches_across_chunks() {
+        let text: String = (0..1500).map(|i| format!("ö{} ", i)).collect();
+        let buffer = TextBuffer::from(text.as_str());
+        assert!(buffer.rope.chunks().count() > 1);
+        let len = buffer.len_chars();
+        for start in (0..len - 6).step_by(7) {
+            let needle = buffer.slice(start..start + 6);
+            let from = start.saturating_sub(3);
+            let byte_from = text.char_indices().nth(from).unwrap().0;
+            let expected = text[byte_from..]
+                .find(&needle)
+                .map(|b| text[..byte_from + b].chars().count());
+            assert_eq!(buffer.find(&needle, from), expected);
+        }
+        assert_eq!(buffer.find("ö1499 ", len - 3), None);
+    }
+}
@@ -0,0 +1,92 @@
+use std::ops::Range;
+
+use crate::TextBuffer;
+
+/// Whether `line` starts a chain, like `~t1: ...` or `out: ...`.
+pub fn is_chain_header(line: &str) -> bool {
+    let line = line.trim_start();
+    let name = line.strip_prefix('~').unwrap_or(line);
+    match name.find(':') {
+        Some(colon) => colon > 0 && name[..colon].chars().all(crate::is_word_char),
+        None => false,
+    }
+}
+
+/// The name a chain header defines, like `~t1` or `out`.
+pub fn chain_name(line: &str) -> Option<&str> {
+    if !is_chain_header(line) {
+        return None;
+    }
+    let line = line.trim_start();
+    line.find(':').map(|colon| &line[..colon])
+}
+
+/// Whether `line` continues a chain with `>>`.
+pub fn is_chain_continuation(line: &str) -> bool {
+    line.trim_start().starts_with(">>")
+}
+
+/// `line` without a leading `//` comment marker, so muted chains are still
+/// recognised as chains.
+pub fn uncommented(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    match trimmed.strip_prefix("//") {
+        Some(rest) => rest,
+        None => line,
+    }
+}
+
+/// The lines of the chain that `line` belongs to: its header and the `>>`
+/// lines after it, commented out or not.
+pub fn chain_at(buffer: &TextBuffer, line: usize) -> Option<Range<usize>> {
+    let mut start = line;
+    while start > 0 && is_chain_continuation(uncommented(&buffer.line(start))) {
+        start -= 1;
+    }
+    if !is_chain_header(uncommented(&buffer.line(start))) {
+        return None;
+    }
+    let mut end = start + 1;
+    while end < buffer.len_lines() && is_chain_continuation(uncommented(&buffer.line(end))) {
+        end += 1;
+    }
+    (line < end).then_some(start..end)
+}
+
+/// The replacements that toggle `//` on `lines`: when every non-blank one
+/// is commented the markers are removed, otherwise `// ` is added at the
+/// smallest indentation.
+pub fn toggle_comment(buffer: &TextBuffer, lines: &[usize]) -> Vec<(Range<usize>, String)> {
+    let texts: Vec<(usize, String)> = lines
+        .iter()
+        .map(|&line| (line, buffer.line(line)))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .collect();
+    let commented = texts
+        .iter()
+        .all(|(_, text)| text.trim_start().starts_with("//"));
+    let indent = texts
+        .iter()
+        .map(|(_, text)| crate::line_indent(text).chars().count())
+        .min()
+        .unwrap_or(0);
+
+    texts
+        .iter()
+        .map(|(line, text)| {
+            let start = buffer.line_to_char(*line);
+            if commented {
+                let at = start + crate::line_indent(text).chars().count();
+                // the space after the marker goes too
+                let len = if text.trim_start().starts_with("// ") {
+                    3
+                } else {
+                    2
+                };
+                (at..at + len, String::new())
+            } else {
+                (start + indent..start + indent, "// ".to_owned())
+            }
+        })
+        .collect()
+}
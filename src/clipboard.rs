@@ -0,0 +1,144 @@
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, rc::Rc};
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+/// System clipboard access.
+///
+/// On desktop this wraps `arboard` and reads synchronously; without a
+/// system clipboard, e.g. on a headless box, copied text stays in here. In
+/// the browser the async Clipboard API is used, so pasted text shows up
+/// later through `take_pending`.
+pub struct Clipboard {
+    #[cfg(not(target_arch = "wasm32"))]
+    inner: Option<arboard::Clipboard>,
+    #[cfg(not(target_arch = "wasm32"))]
+    local: Option<String>,
+    #[cfg(target_arch = "wasm32")]
+    pending: Rc<RefCell<Option<String>>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clipboard {
+    pub fn new() -> Self {
+        let inner = match arboard::Clipboard::new() {
+            Ok(clipboard) => Some(clipboard),
+            Err(e) => {
+                tracing::warn!("clipboard not available: {}", e);
+                None
+            }
+        };
+        Self { inner, local: None }
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        match &mut self.inner {
+            Some(clipboard) => {
+                if let Err(e) = clipboard.set_text(text) {
+                    tracing::warn!("cannot copy to clipboard: {}", e);
+                }
+            }
+            None => self.local = Some(text),
+        }
+    }
+
+    pub fn get_text(&mut self) -> Option<String> {
+        let Some(clipboard) = self.inner.as_mut() else {
+            return self.local.clone();
+        };
+        match clipboard.get_text() {
+            Ok(text) => Some(normalize_newlines(text)),
+            Err(e) => {
+                tracing::warn!("cannot paste from clipboard: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn take_pending(&mut self) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Clipboard {
+    pub fn new() -> Self {
+        Self {
+            pending: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    fn navigator_clipboard() -> Option<JsValue> {
+        let navigator = web_sys::window()?.navigator();
+        let clipboard = js_sys::Reflect::get(&navigator, &"clipboard".into()).ok()?;
+        if clipboard.is_undefined() {
+            log::warn!("clipboard API not available");
+            return None;
+        }
+        Some(clipboard)
+    }
+
+    fn call(clipboard: &JsValue, method: &str, args: &js_sys::Array) -> Option<js_sys::Promise> {
+        let function = js_sys::Reflect::get(clipboard, &method.into())
+            .ok()?
+            .dyn_into::<js_sys::Function>()
+            .ok()?;
+        function
+            .apply(clipboard, args)
+            .ok()?
+            .dyn_into::<js_sys::Promise>()
+            .ok()
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        let Some(clipboard) = Self::navigator_clipboard() else {
+            return;
+        };
+        let args = js_sys::Array::of1(&text.into());
+        if let Some(promise) = Self::call(&clipboard, "writeText", &args) {
+            wasm_bindgen_futures::spawn_local(async move {
+                if let Err(e) = wasm_bindgen_futures::JsFuture::from(promise).await {
+                    log::warn!("cannot copy to clipboard: {:?}", e);
+                }
+            });
+        }
+    }
+
+    /// Starts reading the clipboard; the text arrives via `take_pending`.
+    pub fn get_text(&mut self) -> Option<String> {
+        let clipboard = Self::navigator_clipboard()?;
+        let promise = Self::call(&clipboard, "readText", &js_sys::Array::new())?;
+        let pending = self.pending.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            match wasm_bindgen_futures::JsFuture::from(promise).await {
+                Ok(text) => {
+                    if let Some(text) = text.as_string() {
+                        *pending.borrow_mut() = Some(normalize_newlines(text));
+                    }
+                }
+                Err(e) => log::warn!("cannot paste from clipboard: {:?}", e),
+            }
+        });
+        None
+    }
+
+    pub fn take_pending(&mut self) -> Option<String> {
+        self.pending.borrow_mut().take()
+    }
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the buffer only knows about `\n` line breaks
+fn normalize_newlines(text: String) -> String {
+    if text.contains('\r') {
+        text.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        text
+    }
+}
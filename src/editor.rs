@@ -0,0 +1,2541 @@
+use std::{
+    cell::{Cell, RefCell, RefMut},
+    ops::Range,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use hashbrown::HashSet;
+use winit::{dpi::PhysicalSize, event::*};
+
+use crate::{
+    is_alt_pressed, is_ctrl_pressed, is_shift_pressed, merge_selections, Chord, Clipboard, Command,
+    Edit, EditKind, Folds, History, InsertAt, Keymap, Motion, Operator, Search, SearchField,
+    Selection, Settings, TextBuffer, TextLayout, TextView, Transaction, Vim, VimCommand, VimMode,
+};
+
+/// What the editor asks of the outside world; the renderer passes these on
+/// to the audio engine and the file system.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+    /// run this code
+    Evaluate(String),
+    /// silence the audio until the next evaluation
+    Panic,
+    /// write this code to this file, see `Editor::mark_saved`
+    Save(PathBuf, String),
+    /// edit this file in a buffer of its own
+    Open(PathBuf),
+    /// open an empty buffer, close this one, or show the next or previous
+    NewBuffer,
+    CloseBuffer,
+    NextBuffer,
+    PreviousBuffer,
+    /// stop the engine of this buffer, which goes back to the shared one
+    StopEngine,
+    /// split the pane of this buffer in two, side by side or one above the
+    /// other, or close it
+    SplitRight,
+    SplitDown,
+    ClosePane,
+    /// focus the pane on that side of this one
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+}
+
+// what the path typed into the bottom panel is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Prompt {
+    SaveAs,
+    Open,
+}
+
+/// The text being edited and everything needed to edit it, without a window
+/// or a GPU: `Renderer` feeds it window events and draws what `view` returns.
+pub struct Editor {
+    buffer: TextBuffer,
+    font: fontdue::Font,
+    cursors: Vec<Selection>,
+    history: History,
+    folds: Folds,
+    settings: Settings,
+    keymap: Keymap,
+    // the find panel, whether it is shown, and where the search started
+    search: Search,
+    search_open: bool,
+    search_origin: usize,
+    // modal editing state, and what the last vim delete or yank took
+    vim: Vim,
+    register: String,
+    register_linewise: bool,
+    // shared by the buffers of a workspace, opened on first use otherwise
+    clipboard: Option<Rc<RefCell<Clipboard>>>,
+    // last pointer position in physical pixels
+    mouse_position: (f64, f64),
+    // first visible line, and the part of a pixel scroll short of a line
+    scroll: usize,
+    scroll_px: f64,
+    // the selection made by the button press while the left button is held
+    drag_origin: Option<Selection>,
+    // the selection select-next-occurrence added last, to search after
+    occurrence: Option<Selection>,
+    // set by a keymap chord with alt, which still types a character after it
+    swallow_char: bool,
+    // time, position and count of the last click, for double/triple clicks
+    last_click: (f64, usize, u32),
+    modifiers: HashSet<VirtualKeyCode>,
+    // the code sent by the last evaluation, and the line it failed on
+    evaluated_code: String,
+    error_line: Option<usize>,
+    // the file being edited and the code last read from or written to it
+    path: Option<PathBuf>,
+    saved_code: String,
+    // opens and saves so far, and whether the code differed from the saved
+    // one at a buffer revision, worked out again after an edit
+    saves: u64,
+    dirty: Cell<Option<(u64, bool)>>,
+    // the path typed into the save-as or open panel while it is open
+    prompt: Option<(Prompt, String)>,
+    // evaluate on an engine of this buffer rather than replacing the graph
+    // of the shared one
+    own_engine: bool,
+    // a message shown until the next key press, and whether it asks to
+    // close again to discard unsaved changes
+    notice: Option<String>,
+    closing: bool,
+    // code recovered after a crash, until it is taken or discarded
+    restore: Option<String>,
+    // the window in physical pixels, for layout and hit testing
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+    effects: Vec<Effect>,
+}
+
+impl Editor {
+    /// An editor for `text` with the cursor at the start, laid out for an
+    /// 800x600 window until `resize` says otherwise.
+    pub fn new(text: &str, settings: Settings, keymap: Keymap) -> Self {
+        let b = include_bytes!("FiraCode-Regular.ttf") as &[u8];
+        let font = fontdue::Font::from_bytes(b, fontdue::FontSettings::default()).unwrap();
+        Self {
+            buffer: TextBuffer::from(text),
+            font,
+            cursors: vec![Selection::cursor(0)],
+            history: History::new(),
+            folds: Folds::default(),
+            settings,
+            keymap,
+            search: Search::default(),
+            search_open: false,
+            search_origin: 0,
+            vim: Vim::default(),
+            register: String::new(),
+            register_linewise: false,
+            clipboard: None,
+            mouse_position: (0.0, 0.0),
+            scroll: 0,
+            scroll_px: 0.0,
+            drag_origin: None,
+            occurrence: None,
+            swallow_char: false,
+            last_click: (f64::NEG_INFINITY, 0, 0),
+            modifiers: HashSet::new(),
+            evaluated_code: String::new(),
+            error_line: None,
+            path: None,
+            saved_code: text.to_owned(),
+            saves: 0,
+            dirty: Cell::new(None),
+            prompt: None,
+            own_engine: false,
+            notice: None,
+            closing: false,
+            restore: None,
+            size: PhysicalSize::new(800, 600),
+            scale_factor: 1.0,
+            effects: vec![],
+        }
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) {
+        self.size = size;
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.to_string()
+    }
+
+    pub fn buffer(&self) -> &TextBuffer {
+        &self.buffer
+    }
+
+    /// The selections, sorted by position.
+    pub fn selections(&self) -> &[Selection] {
+        &self.cursors
+    }
+
+    /// Where each caret is, as char indices.
+    pub fn cursors(&self) -> Vec<usize> {
+        self.cursors.iter().map(|s| s.head).collect()
+    }
+
+    /// Replaces the selections; overlapping ones are merged.
+    pub fn set_selections(&mut self, selections: Vec<Selection>) {
+        assert!(!selections.is_empty(), "there is always a cursor");
+        let len = self.buffer.len_chars();
+        self.cursors = selections
+            .into_iter()
+            .map(|s| Selection::new(s.anchor.min(len), s.head.min(len)))
+            .collect();
+        merge_selections(&mut self.cursors);
+        self.history.seal();
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn font(&self) -> &fontdue::Font {
+        &self.font
+    }
+
+    /// The line shown at the top of the window.
+    pub fn first_line(&self) -> usize {
+        self.scroll
+    }
+
+    /// The evaluations and panics asked for since the last call.
+    pub fn take_effects(&mut self) -> Vec<Effect> {
+        std::mem::take(&mut self.effects)
+    }
+
+    /// Marks the line of the evaluated code that the audio engine reported
+    /// `message` for.
+    pub fn set_engine_error(&mut self, message: &str) {
+        self.error_line = crate::error_line(&self.evaluated_code, message);
+    }
+
+    /// The file being edited, if the code has been read from or saved to
+    /// one.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Whether the code differs from what was last opened or saved.
+    pub fn is_dirty(&self) -> bool {
+        let revision = self.buffer.revision();
+        if let Some((_, dirty)) = self.dirty.get().filter(|(at, _)| *at == revision) {
+            return dirty;
+        }
+        let dirty = self.buffer.to_string() != self.saved_code;
+        self.dirty.set(Some((revision, dirty)));
+        dirty
+    }
+
+    /// Changes whenever the code or what it was last saved as does.
+    pub fn revision(&self) -> u64 {
+        self.buffer.revision() + self.saves
+    }
+
+    /// Records that `code` is what `path` now holds.
+    pub fn mark_saved(&mut self, path: PathBuf, code: String) {
+        self.path = Some(path);
+        self.set_saved_code(code);
+    }
+
+    fn set_saved_code(&mut self, code: String) {
+        self.saved_code = code;
+        self.saves += 1;
+        self.dirty.set(None);
+    }
+
+    /// Takes in `code` that another program wrote to the file, in one undo
+    /// step. False when the file holds what was last opened or saved, e.g.
+    /// after our own save.
+    pub fn reload(&mut self, code: String) -> bool {
+        if code == self.saved_code {
+            return false;
+        }
+        let dirty = self.is_dirty();
+        self.replace_text(&code);
+        self.set_saved_code(code);
+        if dirty {
+            self.notice =
+                Some("the file changed on disk: ctrl + z brings back your edits".to_owned());
+        }
+        if self.settings.evaluate_on_reload {
+            self.evaluate();
+        }
+        true
+    }
+
+    /// Offers to replace the code with `code` recovered after a crash: y
+    /// takes it, n or escape keeps the code as it is.
+    pub fn offer_restore(&mut self, code: String) {
+        self.notice =
+            Some("found unsaved changes from a crash: y restores them, n discards them".to_owned());
+        self.restore = Some(code);
+    }
+
+    /// Whether a restore offered after a crash is waiting for an answer.
+    pub fn is_offering_restore(&self) -> bool {
+        self.restore.is_some()
+    }
+
+    // replaces only the part of the text that differs from `code`, so
+    // cursors before and after it stay where they were
+    fn replace_text(&mut self, code: &str) {
+        let old: Vec<char> = self.buffer.chars().collect();
+        let new: Vec<char> = code.chars().collect();
+        let start = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[start..]
+            .iter()
+            .rev()
+            .zip(new[start..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+        if start < old_end || start < new_end {
+            let mut transaction = Transaction::new(EditKind::Other, self.cursors.clone());
+            let text: String = new[start..new_end].iter().collect();
+            let edit = Edit::replace(&self.buffer, start..old_end, &text);
+            transaction.apply(&mut self.buffer, edit);
+            // a cursor inside the replaced text keeps its offset into it
+            let map = |pos: usize| {
+                if pos <= start {
+                    pos
+                } else if pos >= old_end {
+                    pos - old_end + new_end
+                } else {
+                    (pos - start).min(new_end - start) + start
+                }
+            };
+            self.cursors = self
+                .cursors
+                .iter()
+                .map(|s| Selection::new(map(s.anchor), map(s.head)))
+                .collect();
+            merge_selections(&mut self.cursors);
+            transaction.cursors_after = self.cursors.clone();
+            self.history.push(transaction);
+            self.history.seal();
+        }
+        self.settle();
+    }
+
+    /// Shows `message` at the bottom of the window until the next key press.
+    pub fn set_notice(&mut self, message: String) {
+        self.notice = Some(message);
+    }
+
+    /// The file name, marked with `*` when there are unsaved changes.
+    pub fn name(&self) -> String {
+        let name = self
+            .path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or("untitled".into(), |name| name.to_string_lossy());
+        let dirty = if self.is_dirty() { "*" } else { "" };
+        format!("{}{}", dirty, name)
+    }
+
+    /// The window title.
+    pub fn title(&self) -> String {
+        format!("{} - glicol-wgpu", self.name())
+    }
+
+    /// Whether evaluating runs the code on an engine of this buffer, next
+    /// to the others, rather than replacing the graph of the shared one.
+    pub fn own_engine(&self) -> bool {
+        self.own_engine
+    }
+
+    /// Whether the buffer may close. With unsaved changes the first request
+    /// is refused with a warning and a second one in a row is the
+    /// confirmation.
+    pub fn request_close(&mut self) -> bool {
+        if !self.is_dirty() || self.closing {
+            return true;
+        }
+        self.notice =
+            Some("unsaved changes: ctrl + s saves, closing again discards them".to_owned());
+        self.closing = true;
+        false
+    }
+
+    /// Pastes clipboard text that arrived after a paste command, as it
+    /// does in the browser; true when there was some.
+    pub fn poll(&mut self) -> bool {
+        let pending = self.clipboard().take_pending();
+        match pending {
+            Some(text) => {
+                self.paste(&text);
+                self.scroll_to_cursor();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Feeds a key script (see [`key_events`]) through [`Editor::input`].
+    pub fn play(&mut self, script: &str) -> Result<(), String> {
+        for event in crate::key_events(script)? {
+            self.input(&event);
+        }
+        Ok(())
+    }
+
+    /// Handles a window event; true when it changed anything.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.restore_input(event) || self.swallow_character(event) {
+            return true;
+        }
+        let dismissed = self.dismiss_notice(event);
+        let handled = if self.prompt_input(event) {
+            true
+        } else if self.find(event) || self.vim_input(event) {
+            self.scroll_to_cursor();
+            true
+        } else if self.detect_modifiers(event) || self.keymap_input(event) || self.scroll(event) {
+            true
+        } else if self.mouse_input(event) || self.input_character(event) {
+            self.scroll_to_cursor();
+            true
+        } else {
+            false
+        };
+        if handled {
+            self.settle();
+        }
+        handled || dismissed
+    }
+
+    // alt + z types `z` on linux and `Ω` on mac right after toggling the
+    // wrap; any other key event, like releasing alt, means it typed nothing
+    fn swallow_character(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ReceivedCharacter(_) => std::mem::take(&mut self.swallow_char),
+            WindowEvent::KeyboardInput { .. } => {
+                self.swallow_char = false;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    // while a restore is offered, keys only answer it
+    fn restore_input(&mut self, event: &WindowEvent) -> bool {
+        if self.restore.is_none() {
+            return false;
+        }
+        match event {
+            WindowEvent::ReceivedCharacter('y' | 'Y') => {
+                let code = self.restore.take().unwrap();
+                self.replace_text(&code);
+                self.notice = None;
+            }
+            WindowEvent::ReceivedCharacter('n' | 'N')
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        ..
+                    },
+                ..
+            } => {
+                self.restore = None;
+                self.notice = None;
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } if !crate::is_modifier(*keycode) => {}
+            WindowEvent::ReceivedCharacter(_) | WindowEvent::Ime(_) => {}
+            _ => return false,
+        }
+        true
+    }
+
+    // a key press other than a modifier hides the notice and takes back a
+    // refused close, unless it closes again
+    fn dismiss_notice(&mut self, event: &WindowEvent) -> bool {
+        let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+            ..
+        } = event
+        else {
+            return false;
+        };
+        if crate::is_modifier(*keycode) {
+            return false;
+        }
+        let chord = Chord::new(*keycode, &self.modifiers);
+        if self.keymap.lookup(chord) != Some((Command::CloseBuffer, false)) {
+            self.closing = false;
+        }
+        self.notice.take().is_some()
+    }
+
+    // keeps the view on the text after an edit, e.g. after an undo the text
+    // may have shrunk under it
+    fn settle(&mut self) {
+        self.scroll = self.scroll.min(self.buffer.len_lines() - 1);
+        self.folds.retain_foldable(&self.buffer);
+        self.scroll = self.layout().visible_line(self.scroll);
+    }
+
+    pub fn detect_modifiers(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } if crate::is_modifier(*keycode) => {
+                self.modifiers.insert(*keycode);
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Released,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } if crate::is_modifier(*keycode) => {
+                self.modifiers.remove(keycode);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // runs the command the keymap binds to the pressed chord, e.g. shift +
+    // enter to play the sound based on self.buffer
+    fn keymap_input(&mut self, event: &WindowEvent) -> bool {
+        let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+            ..
+        } = event
+        else {
+            return false;
+        };
+        let chord = Chord::new(*keycode, &self.modifiers);
+        let Some((command, extend)) = self.keymap.lookup(chord) else {
+            return false;
+        };
+        if !self.run_command(command, extend) {
+            return false;
+        }
+        self.swallow_char = chord.alt;
+        if command.is_navigation()
+            || matches!(
+                command,
+                Command::Copy
+                    | Command::Cut
+                    | Command::Paste
+                    | Command::AddCursorAbove
+                    | Command::AddCursorBelow
+                    | Command::SelectNextOccurrence
+                    | Command::SingleCursor
+                    | Command::ToggleComment
+                    | Command::ToggleChainComment
+                    | Command::MuteChain
+            )
+        {
+            // moving the cursor ends the current group of typing
+            self.history.seal();
+        }
+        if !matches!(
+            command,
+            Command::Evaluate
+                | Command::Panic
+                | Command::Save
+                | Command::SaveAs
+                | Command::Open
+                | Command::NewBuffer
+                | Command::CloseBuffer
+                | Command::NextBuffer
+                | Command::PreviousBuffer
+                | Command::ToggleEngine
+                | Command::SplitRight
+                | Command::SplitDown
+                | Command::ClosePane
+                | Command::FocusLeft
+                | Command::FocusRight
+                | Command::FocusUp
+                | Command::FocusDown
+        ) {
+            self.scroll_to_cursor();
+        }
+        true
+    }
+
+    /// Runs an editor command; `extend` makes navigation extend the
+    /// selections. Returns false when the command has nothing to do.
+    pub fn run_command(&mut self, command: Command, extend: bool) -> bool {
+        match command {
+            Command::Evaluate => self.evaluate(),
+            Command::Panic => self.panic(),
+            Command::Save => match &self.path {
+                Some(path) => {
+                    let save = Effect::Save(path.clone(), self.buffer.to_string());
+                    self.effects.push(save);
+                }
+                None => self.open_prompt(Prompt::SaveAs),
+            },
+            Command::SaveAs => self.open_prompt(Prompt::SaveAs),
+            Command::Open => self.open_prompt(Prompt::Open),
+            Command::NewBuffer => self.effects.push(Effect::NewBuffer),
+            Command::CloseBuffer => {
+                if self.request_close() {
+                    self.effects.push(Effect::CloseBuffer);
+                }
+            }
+            Command::NextBuffer => self.effects.push(Effect::NextBuffer),
+            Command::PreviousBuffer => self.effects.push(Effect::PreviousBuffer),
+            Command::SplitRight => self.effects.push(Effect::SplitRight),
+            Command::SplitDown => self.effects.push(Effect::SplitDown),
+            Command::ClosePane => self.effects.push(Effect::ClosePane),
+            Command::FocusLeft => self.effects.push(Effect::FocusLeft),
+            Command::FocusRight => self.effects.push(Effect::FocusRight),
+            Command::FocusUp => self.effects.push(Effect::FocusUp),
+            Command::FocusDown => self.effects.push(Effect::FocusDown),
+            Command::ToggleEngine => {
+                self.own_engine = !self.own_engine;
+                let notice = if self.own_engine {
+                    "evaluating runs this buffer on an engine of its own"
+                } else {
+                    self.effects.push(Effect::StopEngine);
+                    "evaluating replaces the graph of the shared engine"
+                };
+                self.notice = Some(notice.to_owned());
+            }
+            Command::Undo | Command::Redo => {
+                let cursors = if command == Command::Undo {
+                    self.history.undo(&mut self.buffer)
+                } else {
+                    self.history.redo(&mut self.buffer)
+                };
+                if let Some(cursors) = cursors {
+                    self.cursors = cursors;
+                }
+            }
+            Command::Copy => self.copy(false),
+            Command::Cut => self.copy(true),
+            Command::Paste => {
+                // in the browser the text arrives later, see `poll`
+                let text = self.clipboard().get_text();
+                if let Some(text) = text {
+                    self.paste(&text);
+                }
+            }
+            Command::AddCursorAbove => self.add_cursor(false),
+            Command::AddCursorBelow => self.add_cursor(true),
+            Command::SelectNextOccurrence => self.select_next_occurrence(),
+            Command::SingleCursor => {
+                if self.cursors.len() == 1 {
+                    return false;
+                }
+                self.cursors.truncate(1);
+            }
+            Command::ToggleComment | Command::ToggleChainComment | Command::MuteChain => {
+                self.comment(command != Command::ToggleComment);
+                if command == Command::MuteChain || self.settings.evaluate_on_comment {
+                    self.evaluate();
+                }
+            }
+            Command::Indent => self.indent(false),
+            Command::Dedent => self.indent(true),
+            Command::ToggleWrap => self.settings.wrap = !self.settings.wrap,
+            Command::Fold | Command::Unfold | Command::ToggleFold => {
+                for i in 0..self.cursors.len() {
+                    let line = self.buffer.char_to_line(self.cursors[i].head);
+                    match command {
+                        Command::Fold => self.folds.fold(&self.buffer, line),
+                        Command::Unfold => self.folds.unfold(&self.buffer, line),
+                        _ => self.folds.toggle(&self.buffer, line),
+                    };
+                }
+                self.clamp_to_folds();
+            }
+            Command::FoldAll => {
+                self.folds.fold_all(&self.buffer);
+                self.clamp_to_folds();
+            }
+            Command::UnfoldAll => self.folds.unfold_all(),
+            Command::Find => self.open_search(false),
+            Command::Replace => self.open_search(true),
+            Command::PageUp => self.page(false, extend),
+            Command::PageDown => self.page(true, extend),
+            Command::DeleteBackward => self.delete(false, false),
+            Command::DeleteForward => self.delete(true, false),
+            Command::DeleteWordBackward => self.delete(false, true),
+            Command::DeleteWordForward => self.delete(true, true),
+            _ => self.move_cursor(command, extend),
+        }
+        true
+    }
+
+    // asks for the whole buffer to be sent to the audio engine
+    fn evaluate(&mut self) {
+        let code = self.buffer.to_string();
+        self.error_line = None;
+        self.evaluated_code = code.clone();
+        self.effects.push(Effect::Evaluate(code));
+        self.history.seal();
+    }
+
+    fn panic(&mut self) {
+        self.effects.push(Effect::Panic);
+    }
+
+    // adds a cursor on the line above the first cursor or below the last
+    fn add_cursor(&mut self, below: bool) {
+        let head = if below {
+            self.cursors.last().unwrap().head
+        } else {
+            self.cursors.first().unwrap().head
+        };
+        let (line, col) = self.buffer.char_to_line_col(head);
+        if below && line + 1 < self.buffer.len_lines() {
+            let pos = self.buffer.line_col_to_char(line + 1, col);
+            self.cursors.push(Selection::cursor(pos));
+        } else if !below && line > 0 {
+            let pos = self.buffer.line_col_to_char(line - 1, col);
+            self.cursors.push(Selection::cursor(pos));
+        }
+        merge_selections(&mut self.cursors);
+    }
+
+    // selects the word under the cursor, then each next occurrence of it
+    fn select_next_occurrence(&mut self) {
+        // the selections are sorted, so the last one is not always the one
+        // added last
+        let last = self
+            .occurrence
+            .filter(|s| self.cursors.contains(s))
+            .unwrap_or(*self.cursors.last().unwrap());
+        if last.is_empty() {
+            if let Some(range) = self.buffer.word_at(last.head) {
+                let word = Selection::new(range.start, range.end);
+                let i = self.cursors.iter().position(|s| *s == last).unwrap();
+                self.cursors[i] = word;
+                self.occurrence = Some(word);
+            }
+        } else {
+            let needle = self.buffer.slice(last.range());
+            // search after the last selection and wrap around
+            let found = self
+                .buffer
+                .find(&needle, last.end())
+                .or_else(|| self.buffer.find(&needle, 0));
+            if let Some(start) = found {
+                let end = start + needle.chars().count();
+                self.cursors.push(Selection::new(start, end));
+                self.occurrence = Some(Selection::new(start, end));
+            }
+        }
+        merge_selections(&mut self.cursors);
+    }
+
+    // copies the selected text to the system clipboard, one line per
+    // selection, and removes it when cutting
+    fn copy(&mut self, cut: bool) {
+        if self.cursors.iter().all(|s| s.is_empty()) {
+            return;
+        }
+        let text = self
+            .cursors
+            .iter()
+            .filter(|s| !s.is_empty())
+            .map(|s| self.buffer.slice(s.range()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.clipboard().set_text(text);
+        if cut {
+            self.edit_selections(EditKind::Other, |_, s| (s.range(), String::new()));
+        }
+    }
+
+    /// Copies to and pastes from `clipboard`, shared with other buffers.
+    pub fn set_clipboard(&mut self, clipboard: Rc<RefCell<Clipboard>>) {
+        self.clipboard = Some(clipboard);
+    }
+
+    fn clipboard(&mut self) -> RefMut<'_, Clipboard> {
+        self.clipboard
+            .get_or_insert_with(Default::default)
+            .borrow_mut()
+    }
+
+    /// Pastes `text` at every cursor, or one line of it per cursor.
+    pub fn paste(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        // one line per cursor when the counts match, otherwise the whole
+        // text at every cursor
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut i = 0;
+        if self.cursors.len() > 1 && lines.len() == self.cursors.len() {
+            self.edit_selections(EditKind::Other, |_, s| {
+                i += 1;
+                (s.range(), lines[i - 1].to_owned())
+            });
+        } else {
+            self.edit_selections(EditKind::Other, |_, s| (s.range(), text.to_owned()));
+        }
+    }
+
+    // indenting adds spaces up to the next indentation stop at each cursor,
+    // or indents whole lines when text is selected; dedenting works on lines
+    fn indent(&mut self, dedent: bool) {
+        let width = self.settings.indent_width;
+        if !dedent && self.cursors.iter().all(|s| s.is_empty()) {
+            self.edit_selections(EditKind::Other, |buffer, selection| {
+                let (_, col) = buffer.char_to_line_col(selection.head);
+                (selection.range(), " ".repeat(width - col % width))
+            });
+            return;
+        }
+
+        let changes = self
+            .selected_lines()
+            .into_iter()
+            .map(|line| {
+                let start = self.buffer.line_to_char(line);
+                if dedent {
+                    let len = crate::dedent_len(&self.buffer.line(line), width);
+                    (start..start + len, String::new())
+                } else {
+                    (start..start, " ".repeat(width))
+                }
+            })
+            .collect();
+        self.replace_ranges(changes);
+    }
+
+    // toggles `//` on the selected lines, or on the whole chain under each
+    // cursor to mute or unmute a track
+    fn comment(&mut self, chain: bool) {
+        let mut lines = self.selected_lines();
+        if chain {
+            lines = lines
+                .into_iter()
+                .flat_map(|line| crate::chain_at(&self.buffer, line).unwrap_or(line..line + 1))
+                .collect();
+            lines.sort_unstable();
+            lines.dedup();
+        }
+        let changes = crate::toggle_comment(&self.buffer, &lines);
+        self.replace_ranges(changes);
+    }
+
+    // every line touched by a selection, in order
+    fn selected_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = self
+            .cursors
+            .iter()
+            .flat_map(|s| {
+                let first = self.buffer.char_to_line(s.start());
+                let mut last = self.buffer.char_to_line(s.end());
+                // a selection ending at the start of a line leaves that line out
+                if last > first && self.buffer.line_to_char(last) == s.end() {
+                    last -= 1;
+                }
+                first..=last
+            })
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    // applies non-overlapping replacements, given top to bottom, as one undo
+    // step and keeps every selection on the text it was on
+    fn replace_ranges(&mut self, changes: Vec<(Range<usize>, String)>) {
+        let changes: Vec<_> = changes
+            .into_iter()
+            .filter(|(range, text)| !range.is_empty() || !text.is_empty())
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        let mut transaction = Transaction::new(EditKind::Other, self.cursors.clone());
+        // bottom up, so the positions above stay valid
+        for (range, text) in changes.iter().rev() {
+            let edit = Edit::replace(&self.buffer, range.clone(), text);
+            transaction.apply(&mut self.buffer, edit);
+        }
+        let shift = |pos: usize| {
+            let mut delta: isize = 0;
+            for (range, text) in &changes {
+                let inserted = text.chars().count() as isize;
+                if pos >= range.end {
+                    delta += inserted - range.len() as isize;
+                } else if pos > range.start {
+                    delta -= (pos - range.start) as isize;
+                }
+            }
+            (pos as isize + delta) as usize
+        };
+        for selection in self.cursors.iter_mut() {
+            *selection = Selection::new(shift(selection.anchor), shift(selection.head));
+        }
+        merge_selections(&mut self.cursors);
+        transaction.cursors_after = self.cursors.clone();
+        self.history.push(transaction);
+    }
+
+    // modal editing when the `vim` setting is on. in insert mode only escape
+    // is taken; in normal and visual mode typed keys are commands, and keys
+    // that are no vim commands (arrows, ctrl shortcuts) work as usual
+    pub fn vim_input(&mut self, event: &WindowEvent) -> bool {
+        if !self.settings.vim {
+            return false;
+        }
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                let visual = self.vim.mode.is_visual();
+                if self.vim.mode == VimMode::Insert {
+                    if *keycode != VirtualKeyCode::Escape {
+                        return false;
+                    }
+                    // the caret steps back onto the last typed char
+                    for selection in self.cursors.iter_mut() {
+                        let (_, col) = self.buffer.char_to_line_col(selection.head);
+                        *selection = Selection::cursor(selection.head - col.min(1));
+                    }
+                    merge_selections(&mut self.cursors);
+                    self.vim.mode = VimMode::Normal;
+                    self.history.seal();
+                    return true;
+                }
+                match keycode {
+                    VirtualKeyCode::Escape => {
+                        self.vim.pending.clear();
+                        if visual {
+                            let head = self.cursors.last().unwrap().head;
+                            self.cursors = vec![Selection::cursor(head)];
+                            self.vim.mode = VimMode::Normal;
+                        }
+                    }
+                    VirtualKeyCode::R if is_ctrl_pressed(&self.modifiers) => {
+                        if let Some(cursors) = self.history.redo(&mut self.buffer) {
+                            self.cursors = cursors;
+                        }
+                    }
+                    VirtualKeyCode::Return if !is_shift_pressed(&self.modifiers) => {
+                        self.vim_command(VimCommand::Move(Motion::Down, None));
+                        self.vim_command(VimCommand::Move(Motion::FirstNonBlank, None));
+                    }
+                    VirtualKeyCode::Back => self.vim_command(VimCommand::Move(Motion::Left, None)),
+                    VirtualKeyCode::Delete if visual => {
+                        self.vim_command(VimCommand::VisualOperate(Operator::Delete))
+                    }
+                    VirtualKeyCode::Delete => self.vim_command(VimCommand::DeleteChar(1)),
+                    VirtualKeyCode::Tab => {}
+                    _ => return false,
+                }
+                self.clamp_to_normal();
+                true
+            }
+            WindowEvent::ReceivedCharacter(_) | WindowEvent::Ime(_)
+                if self.vim.mode != VimMode::Insert =>
+            {
+                let Some(text) = crate::text_from_event(event, &self.modifiers) else {
+                    return false;
+                };
+                for ch in text.chars() {
+                    if let Some(command) = self.vim.feed(ch) {
+                        self.vim_command(command);
+                    }
+                }
+                self.clamp_to_normal();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn vim_command(&mut self, command: VimCommand) {
+        let buffer = &self.buffer;
+        match command {
+            VimCommand::Move(motion, count) => {
+                if self.vim.mode.is_visual() {
+                    let head = self.cursors.last().unwrap().head;
+                    self.set_visual_head(crate::motion_target(buffer, head, motion, count));
+                } else {
+                    for selection in self.cursors.iter_mut() {
+                        let pos = crate::motion_target(buffer, selection.head, motion, count);
+                        *selection = Selection::cursor(pos);
+                    }
+                    merge_selections(&mut self.cursors);
+                }
+            }
+            VimCommand::Operate(operator, target, count) => {
+                let ranges = self
+                    .cursors
+                    .iter()
+                    .filter_map(|s| crate::target_range(buffer, s.head, operator, target, count))
+                    .collect();
+                self.vim_operate(operator, ranges);
+            }
+            VimCommand::DeleteChar(count) => {
+                let ranges = self
+                    .cursors
+                    .iter()
+                    .map(|s| {
+                        let end = buffer.line_end(buffer.char_to_line(s.head));
+                        (s.head..s.head.saturating_add(count).min(end), false)
+                    })
+                    .collect();
+                self.vim_operate(Operator::Delete, ranges);
+            }
+            VimCommand::Put { before, count } => self.vim_put(before, count),
+            VimCommand::Undo(count) => {
+                for _ in 0..count {
+                    if let Some(cursors) = self.history.undo(&mut self.buffer) {
+                        self.cursors = cursors;
+                    }
+                }
+                for selection in self.cursors.iter_mut() {
+                    *selection = Selection::cursor(selection.start());
+                }
+            }
+            VimCommand::Insert(at) => self.vim_insert(at),
+            VimCommand::Visual(mode) => {
+                let head = self.cursors.last().unwrap().head;
+                if self.vim.mode == mode {
+                    self.cursors = vec![Selection::cursor(head)];
+                    self.vim.mode = VimMode::Normal;
+                } else {
+                    if !self.vim.mode.is_visual() {
+                        self.vim.visual_anchor = head;
+                    }
+                    self.vim.mode = mode;
+                    self.set_visual_head(head);
+                }
+            }
+            VimCommand::VisualOperate(operator) => {
+                let anchor = self.vim.visual_anchor;
+                let head = self.cursors.last().unwrap().head;
+                let range = if self.vim.mode == VimMode::VisualLine {
+                    let (a, b) = (buffer.char_to_line(anchor), buffer.char_to_line(head));
+                    (a.min(b)..a.max(b) + 1, true)
+                } else {
+                    let end = (anchor.max(head) + 1).min(buffer.len_chars());
+                    (anchor.min(head)..end, false)
+                };
+                self.cursors = vec![Selection::cursor(anchor.min(head))];
+                self.vim.mode = VimMode::Normal;
+                self.vim_operate(operator, vec![range]);
+            }
+        }
+    }
+
+    // shows the visual selection from `vim.visual_anchor` to `head`; the
+    // block caret covers the char at the head, so charwise selections leave
+    // it out and still look inclusive
+    fn set_visual_head(&mut self, head: usize) {
+        let anchor = self.vim.visual_anchor;
+        let selection = if self.vim.mode == VimMode::VisualLine {
+            let (a, h) = (
+                self.buffer.char_to_line(anchor),
+                self.buffer.char_to_line(head),
+            );
+            if h >= a {
+                Selection::new(self.buffer.line_to_char(a), self.buffer.line_end(h))
+            } else {
+                Selection::new(self.buffer.line_end(a), self.buffer.line_to_char(h))
+            }
+        } else if head >= anchor {
+            Selection::new(anchor, head)
+        } else {
+            Selection::new((anchor + 1).min(self.buffer.len_chars()), head)
+        };
+        self.cursors = vec![selection];
+    }
+
+    // in normal mode there are no selections and the caret sits on a char
+    fn clamp_to_normal(&mut self) {
+        if self.vim.mode != VimMode::Normal {
+            return;
+        }
+        for selection in self.cursors.iter_mut() {
+            let line = self.buffer.char_to_line(selection.head);
+            let end = self.buffer.line_end(line);
+            let head = if selection.head == end && self.buffer.line_len(line) > 0 {
+                end - 1
+            } else {
+                selection.head
+            };
+            *selection = Selection::cursor(head);
+        }
+        merge_selections(&mut self.cursors);
+    }
+
+    // applies `operator` to char ranges, or line ranges when flagged, one
+    // per cursor
+    fn vim_operate(&mut self, operator: Operator, mut ranges: Vec<(Range<usize>, bool)>) {
+        ranges.sort_by_key(|(range, _)| range.start);
+        ranges.dedup_by(|b, a| b.0.start < a.0.end && b.1 == a.1);
+        if ranges.is_empty() {
+            return;
+        }
+        let linewise = ranges[0].1;
+        let buffer = &self.buffer;
+        let spans: Vec<Range<usize>> = ranges
+            .iter()
+            .map(|(range, linewise)| {
+                if *linewise {
+                    crate::line_span(buffer, range.clone())
+                } else {
+                    range.clone()
+                }
+            })
+            .collect();
+
+        // the register keeps whole lines with their line break
+        let taken: Vec<String> = ranges
+            .iter()
+            .map(|(range, linewise)| {
+                if *linewise {
+                    let lines: Vec<String> = range.clone().map(|l| buffer.line(l)).collect();
+                    format!("{}\n", lines.join("\n"))
+                } else {
+                    buffer.slice(range.clone())
+                }
+            })
+            .collect();
+        self.register = taken.concat();
+        self.register_linewise = linewise;
+
+        match operator {
+            Operator::Yank => {
+                // only a yank is meant for other programs; `x` and `dd`
+                // leave the system clipboard alone
+                let text = self.register.clone();
+                self.clipboard().set_text(text);
+                if !linewise {
+                    self.cursors = spans.iter().map(|r| Selection::cursor(r.start)).collect();
+                }
+            }
+            Operator::Delete => {
+                self.cursors = spans.iter().map(|r| Selection::cursor(r.start)).collect();
+                self.replace_ranges(spans.into_iter().map(|r| (r, String::new())).collect());
+                if linewise {
+                    for selection in self.cursors.iter_mut() {
+                        let line = self.buffer.char_to_line(selection.head);
+                        *selection = Selection::cursor(self.buffer.line_indent_end(line));
+                    }
+                }
+            }
+            Operator::Change => {
+                // changing lines keeps one line and its indentation
+                let changes: Vec<Range<usize>> = ranges
+                    .iter()
+                    .zip(spans)
+                    .map(|((range, linewise), span)| {
+                        if *linewise {
+                            buffer.line_indent_end(range.start)..buffer.line_end(range.end - 1)
+                        } else {
+                            span
+                        }
+                    })
+                    .collect();
+                self.cursors = changes.iter().map(|r| Selection::cursor(r.start)).collect();
+                self.replace_ranges(changes.into_iter().map(|r| (r, String::new())).collect());
+                self.vim.mode = VimMode::Insert;
+            }
+        }
+        merge_selections(&mut self.cursors);
+        self.history.seal();
+    }
+
+    // `p` puts the register after the cursor, or below the line for whole
+    // lines; `P` before the cursor or above the line
+    fn vim_put(&mut self, before: bool, count: usize) {
+        if self.register.is_empty() {
+            return;
+        }
+        // a few megabytes at most, whatever the count
+        let count = count.min((16 << 20) / self.register.len()).max(1);
+        let text = self.register.repeat(count);
+        let linewise = self.register_linewise;
+        let len = text.chars().count();
+        let indent = crate::line_indent(&text).chars().count();
+        self.edit_selections_with(EditKind::Other, |buffer, selection| {
+            let line = buffer.char_to_line(selection.head);
+            if linewise && before {
+                let at = buffer.line_to_char(line);
+                (at..at, text.clone(), Selection::cursor(indent))
+            } else if linewise {
+                let at = buffer.line_end(line);
+                let lines = text.strip_suffix('\n').unwrap_or(&text);
+                (
+                    at..at,
+                    format!("\n{}", lines),
+                    Selection::cursor(1 + indent),
+                )
+            } else {
+                let at = if before {
+                    selection.head
+                } else {
+                    (selection.head + 1).min(buffer.line_end(line))
+                };
+                (at..at, text.clone(), Selection::cursor(len - 1))
+            }
+        });
+        self.history.seal();
+    }
+
+    fn vim_insert(&mut self, at: InsertAt) {
+        let width = self.settings.indent_width;
+        let buffer = &self.buffer;
+        match at {
+            InsertAt::Before => {}
+            InsertAt::After | InsertAt::LineStart | InsertAt::LineEnd => {
+                for selection in self.cursors.iter_mut() {
+                    let line = buffer.char_to_line(selection.head);
+                    let pos = match at {
+                        InsertAt::After => (selection.head + 1).min(buffer.line_end(line)),
+                        InsertAt::LineStart => buffer.line_indent_end(line),
+                        _ => buffer.line_end(line),
+                    };
+                    *selection = Selection::cursor(pos);
+                }
+            }
+            InsertAt::LineBelow => {
+                self.edit_selections(EditKind::Other, |buffer, selection| {
+                    let end = buffer.line_end(buffer.char_to_line(selection.head));
+                    let indent = crate::newline_indent(buffer, end, width);
+                    (end..end, format!("\n{}", indent))
+                });
+            }
+            InsertAt::LineAbove => {
+                self.edit_selections_with(EditKind::Other, |buffer, selection| {
+                    let line = buffer.char_to_line(selection.head);
+                    let start = buffer.line_to_char(line);
+                    let indent = crate::line_indent(&buffer.line(line)).to_owned();
+                    let len = indent.chars().count();
+                    (
+                        start..start,
+                        format!("{}\n", indent),
+                        Selection::cursor(len),
+                    )
+                });
+            }
+        }
+        self.vim.mode = VimMode::Insert;
+        self.history.seal();
+    }
+
+    /// Where everything goes in the window.
+    pub fn layout(&self) -> TextLayout {
+        let mut layout = TextLayout::new(
+            self.size,
+            self.scale_factor,
+            &self.font,
+            self.scroll,
+            self.buffer.len_lines(),
+        );
+        layout.status_lines = self.status_lines();
+        layout.wrap = self.settings.wrap;
+        layout.wrap_indent = self.settings.indent_width;
+        layout.folds = self.folds.hidden_lines(&self.buffer);
+        layout
+    }
+
+    fn status_lines(&self) -> usize {
+        let search = match (self.search_open, self.search.replacing) {
+            (false, _) => 0,
+            (true, false) => 1,
+            (true, true) => 2,
+        };
+        search
+            + self.prompt.is_some() as usize
+            + self.notice.is_some() as usize
+            + self.settings.vim as usize
+    }
+
+    // the rows of the find, save-as or open panel, the notice and the vim mode
+    // line, and the caret of the panel; `matches` is `None` when the query
+    // is not a valid regex
+    fn status(&self, matches: Option<&[Range<usize>]>) -> (Vec<String>, Option<(usize, usize)>) {
+        let (mut rows, mut caret) = self.search_status(matches);
+        if let Some((prompt, path)) = &self.prompt {
+            let label = match prompt {
+                Prompt::SaveAs => "save as",
+                Prompt::Open => "open",
+            };
+            let row = format!("{}: {}", label, path);
+            caret = Some((rows.len(), row.chars().count()));
+            rows.push(row);
+        }
+        if let Some(notice) = &self.notice {
+            rows.push(notice.clone());
+        }
+        if self.settings.vim {
+            rows.push(format!("{}  {}", self.vim.mode.label(), self.vim.pending));
+        }
+        (rows, caret)
+    }
+
+    fn search_status(
+        &self,
+        matches: Option<&[Range<usize>]>,
+    ) -> (Vec<String>, Option<(usize, usize)>) {
+        if !self.search_open {
+            return (vec![], None);
+        }
+        let search = &self.search;
+        let mut info = match matches {
+            None => "invalid regex".to_owned(),
+            Some(matches) => {
+                let primary = self.cursors.last().unwrap().range();
+                match matches.iter().position(|m| *m == primary) {
+                    Some(i) => format!("{} of {}", i + 1, matches.len()),
+                    None => format!("{} matches", matches.len()),
+                }
+            }
+        };
+        if search.regex {
+            info.push_str(", regex");
+        }
+        if search.whole_word {
+            info.push_str(", whole word");
+        }
+        let find = format!("find: {}", search.query);
+        let mut caret = (0, find.chars().count());
+        let mut rows = vec![format!("{}   ({})", find, info)];
+        if search.replacing {
+            let replace = format!("replace: {}", search.replacement);
+            if search.field == SearchField::Replacement {
+                caret = (1, replace.chars().count());
+            }
+            rows.push(replace);
+        }
+        (rows, Some(caret))
+    }
+
+    // ctrl + f opens the find panel and ctrl + h find and replace. while it
+    // is open, typing edits the focused field, enter / shift + enter (or f3)
+    // jump between matches, tab switches fields, left alt + r / w toggle
+    // regex and whole-word matching, enter in the replace field replaces
+    // the current match, ctrl + alt + enter all of them, escape closes
+    pub fn find(&mut self, event: &WindowEvent) -> bool {
+        let ctrl = is_ctrl_pressed(&self.modifiers);
+        let shift = is_shift_pressed(&self.modifiers);
+        // right alt is AltGr on many layouts and types characters
+        let left_alt = self.modifiers.contains(&VirtualKeyCode::LAlt);
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                if !self.search_open {
+                    return false;
+                }
+                match keycode {
+                    VirtualKeyCode::Escape => self.search_open = false,
+                    VirtualKeyCode::Tab if self.search.replacing => {
+                        self.search.field = match self.search.field {
+                            SearchField::Query => SearchField::Replacement,
+                            SearchField::Replacement => SearchField::Query,
+                        };
+                    }
+                    VirtualKeyCode::Back => {
+                        self.search.field_mut().pop();
+                        self.search_incremental();
+                    }
+                    VirtualKeyCode::R if left_alt => {
+                        self.search.regex = !self.search.regex;
+                        self.search_incremental();
+                    }
+                    VirtualKeyCode::W if left_alt => {
+                        self.search.whole_word = !self.search.whole_word;
+                        self.search_incremental();
+                    }
+                    VirtualKeyCode::Return if ctrl && is_alt_pressed(&self.modifiers) => {
+                        self.replace_all();
+                    }
+                    VirtualKeyCode::Return
+                        if !shift && self.search.field == SearchField::Replacement =>
+                    {
+                        self.replace_one();
+                    }
+                    VirtualKeyCode::Return | VirtualKeyCode::F3 => self.find_next(!shift),
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::ReceivedCharacter(_) | WindowEvent::Ime(_) if self.search_open => {
+                if left_alt {
+                    // the character typed by an alt + r / w toggle
+                    return true;
+                }
+                match crate::text_from_event(event, &self.modifiers) {
+                    Some(text) => {
+                        self.search.field_mut().push_str(&text.replace('\n', " "));
+                        self.search_incremental();
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // the save-as panel starts from the current path, the open panel from
+    // its directory
+    fn open_prompt(&mut self, prompt: Prompt) {
+        self.search_open = false;
+        let path = match (prompt, &self.path) {
+            (_, None) => String::new(),
+            (Prompt::SaveAs, Some(path)) => path.display().to_string(),
+            (Prompt::Open, Some(path)) => match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => {
+                    format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR)
+                }
+                _ => String::new(),
+            },
+        };
+        self.prompt = Some((prompt, path));
+    }
+
+    // while the save-as or open panel is open, typing edits the path, enter
+    // saves or opens and escape cancels. a path without an extension gets
+    // `.glicol`
+    fn prompt_input(&mut self, event: &WindowEvent) -> bool {
+        let Some((kind, prompt)) = &mut self.prompt else {
+            return false;
+        };
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => match keycode {
+                VirtualKeyCode::Escape => self.prompt = None,
+                VirtualKeyCode::Back => {
+                    prompt.pop();
+                }
+                VirtualKeyCode::Return => {
+                    let mut path = PathBuf::from(prompt.trim());
+                    let kind = *kind;
+                    self.prompt = None;
+                    if path.as_os_str().is_empty() {
+                        return true;
+                    }
+                    if path.extension().is_none() {
+                        path.set_extension("glicol");
+                    }
+                    let effect = match kind {
+                        Prompt::SaveAs => Effect::Save(path, self.buffer.to_string()),
+                        Prompt::Open => Effect::Open(path),
+                    };
+                    self.effects.push(effect);
+                }
+                // modifiers are still tracked; any other key stays in the
+                // panel instead of moving or editing the code behind it
+                keycode if crate::is_modifier(*keycode) => return false,
+                _ => {}
+            },
+            WindowEvent::ReceivedCharacter(_) | WindowEvent::Ime(_) => {
+                if let Some(text) = crate::text_from_event(event, &self.modifiers) {
+                    prompt.push_str(&text.replace('\n', ""));
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn open_search(&mut self, replacing: bool) {
+        let primary = *self.cursors.last().unwrap();
+        // a selection on one line becomes the query
+        let selected = self.buffer.slice(primary.range());
+        if !selected.is_empty() && !selected.contains('\n') {
+            self.search.query = selected;
+        }
+        self.search.replacing = replacing;
+        self.search.field = if replacing {
+            SearchField::Replacement
+        } else {
+            SearchField::Query
+        };
+        self.search_open = true;
+        self.search_origin = primary.start();
+        self.search_incremental();
+    }
+
+    // selects the first match from where the search started, so typing
+    // refines the query in place
+    fn search_incremental(&mut self) {
+        if self.search.field != SearchField::Query {
+            return;
+        }
+        let Ok(matches) = self.search.matches(&self.buffer) else {
+            return;
+        };
+        let found = matches
+            .iter()
+            .find(|m| m.start >= self.search_origin)
+            .or(matches.first());
+        if let Some(m) = found {
+            self.cursors = vec![Selection::new(m.start, m.end)];
+            self.history.seal();
+        }
+    }
+
+    fn find_next(&mut self, forward: bool) {
+        let Ok(matches) = self.search.matches(&self.buffer) else {
+            return;
+        };
+        let primary = *self.cursors.last().unwrap();
+        let found = if forward {
+            matches
+                .iter()
+                .find(|m| m.start >= primary.end() && **m != primary.range())
+                .or(matches.first())
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|m| m.end <= primary.start())
+                .or(matches.last())
+        };
+        if let Some(m) = found {
+            self.cursors = vec![Selection::new(m.start, m.end)];
+            self.search_origin = m.start;
+            self.history.seal();
+        }
+    }
+
+    // replaces the selected match, if it is one, and moves on to the next
+    fn replace_one(&mut self) {
+        let Ok(replacements) = self.search.replacements(&self.buffer) else {
+            return;
+        };
+        let primary = self.cursors.last().unwrap().range();
+        if let Some((range, text)) = replacements.into_iter().find(|(r, _)| *r == primary) {
+            let mut transaction = Transaction::new(EditKind::Other, self.cursors.clone());
+            let edit = Edit::replace(&self.buffer, range.clone(), &text);
+            transaction.apply(&mut self.buffer, edit);
+            self.cursors = vec![Selection::cursor(range.start + text.chars().count())];
+            transaction.cursors_after = self.cursors.clone();
+            self.history.push(transaction);
+        }
+        self.find_next(true);
+    }
+
+    // every match in one undo step
+    fn replace_all(&mut self) {
+        let Ok(replacements) = self.search.replacements(&self.buffer) else {
+            return;
+        };
+        let Some((first, _)) = replacements.first() else {
+            return;
+        };
+        let first = first.start;
+        let mut transaction = Transaction::new(EditKind::Other, self.cursors.clone());
+        // bottom up, so the positions above stay valid
+        for (range, text) in replacements.iter().rev() {
+            let edit = Edit::replace(&self.buffer, range.clone(), text);
+            transaction.apply(&mut self.buffer, edit);
+        }
+        self.cursors = vec![Selection::cursor(first)];
+        transaction.cursors_after = self.cursors.clone();
+        self.history.push(transaction);
+        self.history.seal();
+    }
+
+    // the mouse wheel scrolls the view
+    pub fn scroll(&mut self, event: &WindowEvent) -> bool {
+        let layout = self.layout();
+        match event {
+            WindowEvent::MouseWheel { delta, .. } => {
+                // trackpads report pixels, so keep the remainder until it
+                // adds up to a whole line
+                self.scroll_px -= match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y as f64 * 3.0 * layout.line_height_px(),
+                    MouseScrollDelta::PixelDelta(position) => position.y,
+                };
+                let lines = (self.scroll_px / layout.line_height_px()).trunc();
+                self.scroll_px -= lines * layout.line_height_px();
+                // folded lines do not count
+                let mut scroll = layout.visible_line(self.scroll);
+                for _ in 0..lines.abs() as usize {
+                    let next = if lines > 0.0 {
+                        layout.next_visible_line(&self.buffer, scroll)
+                    } else {
+                        layout.prev_visible_line(scroll)
+                    };
+                    match next {
+                        Some(line) => scroll = line,
+                        None => break,
+                    }
+                }
+                self.scroll = scroll;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // moves the cursors and the view by a page
+    fn page(&mut self, down: bool, extend: bool) {
+        let layout = self.layout();
+        let page = layout.visible_lines().saturating_sub(1).max(1);
+        let last_line = self.buffer.len_lines() - 1;
+        for selection in self.cursors.iter_mut() {
+            let (line, col) = self.buffer.char_to_line_col(selection.head);
+            let line = layout.visible_line(if down {
+                (line + page).min(last_line)
+            } else {
+                line.saturating_sub(page)
+            });
+            *selection = selection.move_to(self.buffer.line_col_to_char(line, col), extend);
+        }
+        merge_selections(&mut self.cursors);
+        self.scroll = if down {
+            (self.scroll + page).min(last_line)
+        } else {
+            self.scroll.saturating_sub(page)
+        };
+    }
+
+    /// Scrolls just enough to bring the last cursor into view.
+    pub fn scroll_to_cursor(&mut self) {
+        self.reveal_cursors();
+        let layout = self.layout();
+        let visible = layout.visible_lines();
+        let head = self.cursors.last().unwrap().head;
+        let line = self.buffer.char_to_line(head);
+        if line < self.scroll {
+            self.scroll = line;
+            return;
+        }
+        // the first line from which the rows down to the cursor still fit,
+        // wrapped lines take more than one
+        let (_, index) = layout.rows_at(&self.buffer, &self.font, head);
+        let mut rows = index + 1;
+        let mut first = line;
+        while first > self.scroll {
+            let Some(above) = layout.prev_visible_line(first) else {
+                break;
+            };
+            let count = layout.line_rows(&self.buffer, &self.font, above).len();
+            if rows + count > visible {
+                break;
+            }
+            rows += count;
+            first = above;
+        }
+        self.scroll = first;
+    }
+
+    // opens the folds that hide a cursor, e.g. one put there by a search,
+    // an undo or a vim motion
+    fn reveal_cursors(&mut self) {
+        let hidden = self.folds.hidden_lines(&self.buffer);
+        if hidden.is_empty() {
+            return;
+        }
+        for selection in self.cursors.iter() {
+            let line = self.buffer.char_to_line(selection.head);
+            if hidden.iter().any(|lines| lines.contains(&line)) {
+                self.folds.unfold(&self.buffer, line);
+            }
+        }
+    }
+
+    // moves the cursors that a new fold hides to the end of its header
+    fn clamp_to_folds(&mut self) {
+        let hidden = self.folds.hidden_lines(&self.buffer);
+        for selection in self.cursors.iter_mut() {
+            let head = crate::skip_hidden(&self.buffer, &hidden, selection.head, false);
+            if head != selection.head {
+                *selection = Selection::cursor(head);
+            }
+        }
+        merge_selections(&mut self.cursors);
+    }
+
+    fn hit_test(&self) -> usize {
+        let (x, y) = self.mouse_position;
+        self.layout().hit_test(&self.buffer, &self.font, x, y)
+    }
+
+    // click places the cursor and dragging selects; a double click selects
+    // a word and a triple click the whole line. shift + click extends the
+    // selection and alt + click adds another cursor
+    pub fn mouse_input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse_position = (position.x, position.y);
+                if let Some(origin) = self.drag_origin {
+                    let pos = self.hit_test();
+                    *self.cursors.last_mut().unwrap() = origin.span_to(pos);
+                    true
+                } else {
+                    false
+                }
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let pos = self.hit_test();
+                // the fold toggle between the line numbers and the text
+                let layout = self.layout();
+                let x = (2.0 * self.mouse_position.0 / layout.width as f64) as f32;
+                let line = self.buffer.char_to_line(pos);
+                if x >= layout.gutter_width
+                    && x < layout.origin_x
+                    && Folds::is_foldable(&self.buffer, line)
+                {
+                    self.folds.toggle(&self.buffer, line);
+                    self.clamp_to_folds();
+                    return true;
+                }
+                let now = crate::now_ms();
+                let (last_time, last_pos, last_count) = self.last_click;
+                let count = if now - last_time < 400.0 && last_pos == pos {
+                    last_count % 3 + 1
+                } else {
+                    1
+                };
+                self.last_click = (now, pos, count);
+
+                let selection = match count {
+                    2 => match self.buffer.word_at(pos) {
+                        Some(range) => Selection::new(range.start, range.end),
+                        None => Selection::cursor(pos),
+                    },
+                    3 => {
+                        let line = self.buffer.char_to_line(pos);
+                        let start = self.buffer.line_to_char(line);
+                        // include the line break so the next line starts the drag
+                        let end = (self.buffer.line_end(line) + 1).min(self.buffer.len_chars());
+                        Selection::new(start, end)
+                    }
+                    _ => Selection::cursor(pos),
+                };
+
+                if count == 1 && is_shift_pressed(&self.modifiers) {
+                    let last = self.cursors.last_mut().unwrap();
+                    self.drag_origin = Some(Selection::cursor(last.anchor));
+                    *last = Selection::new(last.anchor, pos);
+                } else {
+                    if is_alt_pressed(&self.modifiers) && count == 1 {
+                        self.cursors.push(selection);
+                    } else if count > 1 {
+                        *self.cursors.last_mut().unwrap() = selection;
+                    } else {
+                        self.cursors = vec![selection];
+                    }
+                    self.drag_origin = Some(selection);
+                }
+                self.history.seal();
+                true
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } if self.drag_origin.is_some() => {
+                self.drag_origin = None;
+                merge_selections(&mut self.cursors);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // moves every cursor, or with `extend` every selection head; left and
+    // right without `extend` collapse a selection to its start or end
+    fn move_cursor(&mut self, command: Command, extend: bool) {
+        let layout = self.layout();
+        for i in 0..self.cursors.len() {
+            let selection = self.cursors[i];
+            let head = selection.head;
+            if let Some(pos) = self.move_on_rows(&layout, command, head) {
+                self.cursors[i] = selection.move_to(pos, extend);
+                continue;
+            }
+            let pos = match command {
+                Command::WordLeft => self.buffer.prev_word_boundary(head),
+                Command::WordRight => self.buffer.next_word_boundary(head),
+                Command::CursorLeft => {
+                    // without shift, a selection collapses to its start
+                    if !extend && !selection.is_empty() {
+                        selection.start()
+                    } else {
+                        head.saturating_sub(1)
+                    }
+                }
+                Command::CursorRight => {
+                    if !extend && !selection.is_empty() {
+                        selection.end()
+                    } else {
+                        (head + 1).min(self.buffer.len_chars())
+                    }
+                }
+                Command::DocumentStart => 0,
+                Command::DocumentEnd => self.buffer.len_chars(),
+                Command::LineStart => {
+                    // first to the indentation, then to the start of the line
+                    let line = self.buffer.char_to_line(head);
+                    let indent_end = self.buffer.line_indent_end(line);
+                    if head == indent_end {
+                        self.buffer.line_to_char(line)
+                    } else {
+                        indent_end
+                    }
+                }
+                Command::LineEnd => self.buffer.line_end(self.buffer.char_to_line(head)),
+                Command::CursorUp => {
+                    let (line, col) = self.buffer.char_to_line_col(head);
+                    // If we're on the first line, we don't move
+                    if line > 0 {
+                        // the column is clamped to the end of the previous line
+                        self.buffer.line_col_to_char(line - 1, col)
+                    } else {
+                        head
+                    }
+                }
+                Command::CursorDown => {
+                    let (line, col) = self.buffer.char_to_line_col(head);
+                    if line + 1 < self.buffer.len_lines() {
+                        self.buffer.line_col_to_char(line + 1, col)
+                    } else {
+                        // on the last line, go to the end of the text
+                        self.buffer.len_chars()
+                    }
+                }
+                _ => head,
+            };
+            // hidden text is stepped over in the direction of the move
+            let pos = crate::skip_hidden(&self.buffer, &layout.folds, pos, pos > head);
+            self.cursors[i] = selection.move_to(pos, extend);
+        }
+        merge_selections(&mut self.cursors);
+    }
+
+    // with soft wrap or folds, up and down go by rows and home and end stop
+    // at the wrap points first; none when the plain line movement applies
+    fn move_on_rows(&self, layout: &TextLayout, command: Command, head: usize) -> Option<usize> {
+        if !layout.wrap && layout.folds.is_empty() {
+            return None;
+        }
+        let (rows, index) = layout.rows_at(&self.buffer, &self.font, head);
+        let row = &rows[index];
+        match command {
+            Command::LineStart if index > 0 && head != row.range.start => Some(row.range.start),
+            Command::LineEnd if index + 1 < rows.len() && head != row.text_end => {
+                Some(row.text_end)
+            }
+            Command::CursorUp | Command::CursorDown => {
+                let x = layout.row_x(&self.buffer, &self.font, row, head);
+                let target = if command == Command::CursorUp {
+                    if index > 0 {
+                        rows[index - 1].clone()
+                    } else if let Some(line) = layout.prev_visible_line(row.line) {
+                        layout
+                            .line_rows(&self.buffer, &self.font, line)
+                            .pop()
+                            .unwrap()
+                    } else {
+                        return Some(head);
+                    }
+                } else if index + 1 < rows.len() {
+                    rows[index + 1].clone()
+                } else if let Some(line) = layout.next_visible_line(&self.buffer, row.line) {
+                    layout.line_rows(&self.buffer, &self.font, line).remove(0)
+                } else if row.folded > 0 {
+                    // the fold runs to the end of the text
+                    return Some(head);
+                } else {
+                    return Some(self.buffer.len_chars());
+                };
+                Some(layout.row_hit(&self.buffer, &self.font, &target, x))
+            }
+            _ => None,
+        }
+    }
+
+    // replaces the text around every cursor in one undo step: `f` gets each
+    // selection, already shifted by the edits before it, and returns the
+    // range to replace and the new text; the cursor ends up after the text
+    fn edit_selections<F>(&mut self, kind: EditKind, mut f: F)
+    where
+        F: FnMut(&TextBuffer, Selection) -> (Range<usize>, String),
+    {
+        self.edit_selections_with(kind, |buffer, selection| {
+            let (range, text) = f(buffer, selection);
+            let len = text.chars().count();
+            (range, text, Selection::cursor(len))
+        });
+    }
+
+    // like `edit_selections`, but `f` also says where the selection goes,
+    // relative to the start of the replaced range
+    fn edit_selections_with<F>(&mut self, kind: EditKind, mut f: F)
+    where
+        F: FnMut(&TextBuffer, Selection) -> (Range<usize>, String, Selection),
+    {
+        let mut transaction = Transaction::new(kind, self.cursors.clone());
+        let mut delta: isize = 0;
+        for i in 0..self.cursors.len() {
+            let selection = self.cursors[i];
+            let shifted = Selection::new(
+                (selection.anchor as isize + delta) as usize,
+                (selection.head as isize + delta) as usize,
+            );
+            let (range, text, after) = f(&self.buffer, shifted);
+            let inserted_len = text.chars().count();
+            delta += inserted_len as isize - range.len() as isize;
+            let edit = Edit::replace(&self.buffer, range.clone(), &text);
+            if !edit.removed.is_empty() || !edit.inserted.is_empty() {
+                transaction.apply(&mut self.buffer, edit);
+            }
+            self.cursors[i] = Selection::new(range.start + after.anchor, range.start + after.head);
+        }
+        merge_selections(&mut self.cursors);
+        transaction.cursors_after = self.cursors.clone();
+        self.history.push(transaction);
+    }
+
+    // removes the selected text, or the character before each cursor, or
+    // after it when `forward`; `by_word` removes up to the word boundary
+    fn delete(&mut self, forward: bool, by_word: bool) {
+        let kind = if self.cursors.iter().any(|s| !s.is_empty()) || by_word {
+            EditKind::Other
+        } else {
+            EditKind::Delete
+        };
+        self.edit_selections(kind, |buffer, selection| {
+            let head = selection.head;
+            let range = if !selection.is_empty() {
+                selection.range()
+            } else if forward && by_word {
+                head..buffer.next_word_boundary(head)
+            } else if forward {
+                head..(head + 1).min(buffer.len_chars())
+            } else if by_word {
+                buffer.prev_word_boundary(head)..head
+            } else if head > 0
+                && buffer.get_char(head).is_some()
+                && crate::closing_pair(buffer.char(head - 1)) == buffer.get_char(head)
+            {
+                // an empty pair goes away as a whole
+                head - 1..head + 1
+            } else {
+                head.saturating_sub(1)..head
+            };
+            (range, String::new())
+        });
+    }
+
+    pub fn input_character(&mut self, event: &WindowEvent) -> bool {
+        match crate::text_from_event(event, &self.modifiers) {
+            Some(text) => {
+                self.insert_text(&text);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Types `text` at every cursor, replacing the selections, with the
+    /// auto-indent and bracket pairing of the keyboard.
+    pub fn insert_text(&mut self, text: &str) {
+        // every new line and every replaced selection gets its own undo step
+        let kind = if text.contains('\n') || self.cursors.iter().any(|s| !s.is_empty()) {
+            EditKind::Other
+        } else {
+            EditKind::Insert
+        };
+        let width = self.settings.indent_width;
+        let mut chars = text.chars();
+        let single = chars.next().filter(|_| chars.next().is_none());
+        self.edit_selections_with(kind, |buffer, selection| {
+            let range = selection.range();
+            let head = selection.head;
+            if let Some(ch) = single {
+                let closes = crate::is_closing(ch) || ch == '"' || ch == '\'';
+                if ch == '\n' {
+                    // a new line starts at the indentation of the chain
+                    let indent = crate::newline_indent(buffer, range.start, width);
+                    let text = format!("\n{}", indent);
+                    let len = text.chars().count();
+                    return (range, text, Selection::cursor(len));
+                } else if closes && selection.is_empty() && buffer.get_char(head) == Some(ch) {
+                    // step over the closing char that is already there
+                    return (range, String::new(), Selection::cursor(1));
+                } else if let Some(close) = crate::closing_pair(ch) {
+                    if !selection.is_empty() {
+                        // brackets and quotes wrap the selection
+                        let inner = buffer.slice(range.clone());
+                        let len = inner.chars().count();
+                        let text = format!("{}{}{}", ch, inner, close);
+                        return (range, text, Selection::new(1, 1 + len));
+                    } else if crate::should_pair(buffer, head, ch) {
+                        let text = format!("{}{}", ch, close);
+                        return (range, text, Selection::cursor(1));
+                    }
+                }
+            }
+            let len = text.chars().count();
+            (range, text.to_owned(), Selection::cursor(len))
+        });
+    }
+
+    /// What the renderer draws.
+    pub fn view(&self) -> TextView<'_> {
+        let matches = if self.search_open {
+            self.search.matches(&self.buffer).ok()
+        } else {
+            Some(vec![])
+        };
+        let (status, status_cursor) = self.status(matches.as_deref());
+        TextView {
+            buffer: &self.buffer,
+            cursors: &self.cursors,
+            changed_lines: crate::changed_lines(&self.evaluated_code, &self.buffer),
+            error_line: self.error_line,
+            matches: matches.unwrap_or_default(),
+            block_caret: self.settings.vim && self.vim.mode != VimMode::Insert,
+            status,
+            status_cursor,
+            brackets: self
+                .cursors
+                .iter()
+                .filter(|s| s.is_empty())
+                .filter_map(|s| crate::matching_bracket(&self.buffer, s.head))
+                .flat_map(|(a, b)| [a, b])
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> Editor {
+        Editor::new(text, Settings::default(), Keymap::default())
+    }
+
+    fn editor_at(text: &str, pos: usize) -> Editor {
+        let mut editor = editor(text);
+        editor.set_selections(vec![Selection::cursor(pos)]);
+        editor
+    }
+
+    fn run(editor: &mut Editor, commands: &[Command]) {
+        for command in commands {
+            editor.run_command(*command, false);
+        }
+    }
+
+    #[test]
+    fn typing_inserts_at_the_cursor() {
+        let mut editor = editor_at("sin 440", 4);
+        editor.insert_text("~");
+        assert_eq!(editor.text(), "sin ~440");
+        assert_eq!(editor.cursors(), vec![5]);
+    }
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut editor = editor("sin 440");
+        editor.set_selections(vec![Selection::new(0, 3)]);
+        editor.insert_text("saw");
+        assert_eq!(editor.text(), "saw 440");
+        assert_eq!(editor.cursors(), vec![3]);
+    }
+
+    #[test]
+    fn new_line_after_a_chain_header_is_indented() {
+        let mut editor = editor_at("~a: sin 440", 11);
+        editor.insert_text("\n");
+        assert_eq!(editor.text(), "~a: sin 440\n    ");
+        assert_eq!(editor.cursors(), vec![16]);
+    }
+
+    #[test]
+    fn brackets_pair_and_step_over() {
+        let mut editor = editor("");
+        editor.insert_text("(");
+        assert_eq!(editor.text(), "()");
+        assert_eq!(editor.cursors(), vec![1]);
+        editor.insert_text(")");
+        assert_eq!(editor.text(), "()");
+        assert_eq!(editor.cursors(), vec![2]);
+    }
+
+    #[test]
+    fn backspace_removes_an_empty_pair() {
+        let mut editor = editor_at("()", 1);
+        run(&mut editor, &[Command::DeleteBackward]);
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn delete_removes_the_next_char() {
+        let mut editor = editor_at("abc", 1);
+        run(&mut editor, &[Command::DeleteForward]);
+        assert_eq!(editor.text(), "ac");
+        assert_eq!(editor.cursors(), vec![1]);
+    }
+
+    #[test]
+    fn word_deletion() {
+        let mut editor = editor_at("sin 440", 7);
+        run(&mut editor, &[Command::DeleteWordBackward]);
+        assert_eq!(editor.text(), "sin ");
+        let mut editor = editor_at("sin 440", 0);
+        run(&mut editor, &[Command::DeleteWordForward]);
+        assert_eq!(editor.text(), " 440");
+    }
+
+    #[test]
+    fn arrows_move_and_clamp_the_column() {
+        let mut editor = editor("abcd\nef\nghij");
+        run(&mut editor, &[Command::CursorRight, Command::CursorRight]);
+        assert_eq!(editor.cursors(), vec![2]);
+        run(&mut editor, &[Command::CursorDown]);
+        assert_eq!(editor.cursors(), vec![7]);
+        run(&mut editor, &[Command::CursorDown]);
+        assert_eq!(editor.cursors(), vec![10]);
+        run(&mut editor, &[Command::CursorDown]);
+        assert_eq!(editor.cursors(), vec![12]);
+        run(&mut editor, &[Command::CursorUp, Command::CursorLeft]);
+        assert_eq!(editor.cursors(), vec![6]);
+    }
+
+    #[test]
+    fn up_from_below_an_empty_first_line() {
+        let mut editor = editor_at("\nabc", 3);
+        run(&mut editor, &[Command::CursorUp]);
+        assert_eq!(editor.cursors(), vec![0]);
+        run(&mut editor, &[Command::CursorUp]);
+        assert_eq!(editor.cursors(), vec![0]);
+    }
+
+    #[test]
+    fn home_goes_to_the_indentation_first() {
+        let mut editor = editor_at("    >> mul 0.5", 14);
+        run(&mut editor, &[Command::LineStart]);
+        assert_eq!(editor.cursors(), vec![4]);
+        run(&mut editor, &[Command::LineStart]);
+        assert_eq!(editor.cursors(), vec![0]);
+        run(&mut editor, &[Command::LineEnd]);
+        assert_eq!(editor.cursors(), vec![14]);
+    }
+
+    #[test]
+    fn document_start_and_end() {
+        let mut editor = editor_at("a\nb\nc", 2);
+        run(&mut editor, &[Command::DocumentEnd]);
+        assert_eq!(editor.cursors(), vec![5]);
+        run(&mut editor, &[Command::DocumentStart]);
+        assert_eq!(editor.cursors(), vec![0]);
+    }
+
+    #[test]
+    fn word_jumps() {
+        let mut editor = editor("~t1: seq 60");
+        run(&mut editor, &[Command::WordRight]);
+        assert_eq!(editor.cursors(), vec![3]);
+        run(&mut editor, &[Command::DocumentEnd, Command::WordLeft]);
+        assert_eq!(editor.cursors(), vec![9]);
+    }
+
+    #[test]
+    fn extending_selects_and_plain_moves_collapse() {
+        let mut editor = editor("abc");
+        editor.run_command(Command::CursorRight, true);
+        editor.run_command(Command::CursorRight, true);
+        assert_eq!(editor.selections(), &[Selection::new(0, 2)]);
+        run(&mut editor, &[Command::CursorLeft]);
+        assert_eq!(editor.selections(), &[Selection::cursor(0)]);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut editor = editor("");
+        editor.insert_text("a");
+        editor.insert_text("b");
+        run(&mut editor, &[Command::Undo]);
+        assert_eq!(editor.text(), "");
+        assert_eq!(editor.cursors(), vec![0]);
+        run(&mut editor, &[Command::Redo]);
+        assert_eq!(editor.text(), "ab");
+        assert_eq!(editor.cursors(), vec![2]);
+    }
+
+    #[test]
+    fn typing_at_several_cursors() {
+        let mut editor = editor("a\nb\nc");
+        run(
+            &mut editor,
+            &[Command::AddCursorBelow, Command::AddCursorBelow],
+        );
+        assert_eq!(editor.cursors(), vec![0, 2, 4]);
+        editor.insert_text("~");
+        assert_eq!(editor.text(), "~a\n~b\n~c");
+        run(&mut editor, &[Command::SingleCursor]);
+        assert_eq!(editor.cursors().len(), 1);
+    }
+
+    #[test]
+    fn select_next_occurrence_adds_selections() {
+        let mut editor = editor("sin sin");
+        run(&mut editor, &[Command::SelectNextOccurrence]);
+        assert_eq!(editor.selections(), &[Selection::new(0, 3)]);
+        run(&mut editor, &[Command::SelectNextOccurrence]);
+        editor.insert_text("saw");
+        assert_eq!(editor.text(), "saw saw");
+    }
+
+    #[test]
+    fn select_next_occurrence_wraps_around_to_every_occurrence() {
+        let mut editor = editor_at("a a a a", 4);
+        run(&mut editor, &[Command::SelectNextOccurrence; 4]);
+        let ranges: Vec<_> = editor.selections().iter().map(|s| s.range()).collect();
+        assert_eq!(ranges, vec![0..1, 2..3, 4..5, 6..7]);
+    }
+
+    #[test]
+    fn toggle_comment_on_the_line() {
+        let mut editor = editor("~a: sin 1\nout: ~a");
+        run(&mut editor, &[Command::ToggleComment]);
+        assert_eq!(editor.text(), "// ~a: sin 1\nout: ~a");
+        run(&mut editor, &[Command::ToggleComment]);
+        assert_eq!(editor.text(), "~a: sin 1\nout: ~a");
+        assert!(editor.take_effects().is_empty());
+    }
+
+    #[test]
+    fn mute_chain_comments_the_chain_and_evaluates() {
+        let mut editor = editor_at("~a: sin 1\n>> mul 2\nout: ~a", 12);
+        run(&mut editor, &[Command::MuteChain]);
+        let text = "// ~a: sin 1\n// >> mul 2\nout: ~a";
+        assert_eq!(editor.text(), text);
+        assert_eq!(
+            editor.take_effects(),
+            vec![Effect::Evaluate(text.to_owned())]
+        );
+    }
+
+    #[test]
+    fn evaluate_and_panic_are_effects() {
+        let mut editor = editor("out: sin 440");
+        run(&mut editor, &[Command::Evaluate, Command::Panic]);
+        assert_eq!(
+            editor.take_effects(),
+            vec![Effect::Evaluate("out: sin 440".to_owned()), Effect::Panic]
+        );
+        assert!(editor.take_effects().is_empty());
+    }
+
+    #[test]
+    fn engine_errors_mark_a_line() {
+        let mut editor = editor("out: sin 440\n~a: foo");
+        run(&mut editor, &[Command::Evaluate]);
+        editor.set_engine_error("parsing error at line[2]");
+        assert_eq!(editor.view().error_line, Some(1));
+    }
+
+    #[test]
+    fn saving_an_untitled_set_asks_for_a_path() {
+        let mut editor = editor("out: sin 440");
+        assert_eq!(editor.title(), "untitled - glicol-wgpu");
+        editor.play("<ctrl+s>sets/a<enter>").unwrap();
+        assert_eq!(editor.text(), "out: sin 440");
+        let path = PathBuf::from("sets/a.glicol");
+        let code = "out: sin 440".to_owned();
+        assert_eq!(
+            editor.take_effects(),
+            vec![Effect::Save(path.clone(), code.clone())]
+        );
+        editor.mark_saved(path.clone(), code.clone());
+        assert_eq!(editor.title(), "a.glicol - glicol-wgpu");
+        editor.play("<ctrl+end>0<ctrl+s>").unwrap();
+        assert_eq!(editor.title(), "*a.glicol - glicol-wgpu");
+        let code = "out: sin 4400".to_owned();
+        assert_eq!(editor.take_effects(), vec![Effect::Save(path, code)]);
+    }
+
+    #[test]
+    fn save_as_can_be_cancelled() {
+        let mut editor = editor("");
+        editor.play("<ctrl+shift+s>x<escape>y").unwrap();
+        assert_eq!(editor.text(), "y");
+        assert!(editor.take_effects().is_empty());
+    }
+
+    #[test]
+    fn the_path_prompt_keeps_keys_from_the_code() {
+        let mut editor = editor("sin 440");
+        editor
+            .play("<ctrl+o><right><ctrl+end><delete><ctrl+z><tab>a<escape>")
+            .unwrap();
+        assert_eq!(editor.text(), "sin 440");
+        assert_eq!(editor.cursors(), vec![0]);
+        assert!(editor.take_effects().is_empty());
+    }
+
+    #[test]
+    fn closing_with_unsaved_changes_needs_a_second_request() {
+        let mut editor = editor("");
+        assert!(editor.request_close());
+        editor.insert_text("a");
+        assert!(editor.is_dirty());
+        assert!(!editor.request_close());
+        assert!(editor.request_close());
+        // a key press in between takes the confirmation back
+        editor.play("<left>").unwrap();
+        assert!(!editor.request_close());
+    }
+
+    #[test]
+    fn the_revision_follows_edits_and_saves() {
+        let mut editor = editor("saw 440");
+        let revision = editor.revision();
+        assert!(!editor.is_dirty());
+        editor.insert_text("a");
+        assert!(editor.is_dirty());
+        run(&mut editor, &[Command::Undo]);
+        assert!(!editor.is_dirty());
+        assert_ne!(editor.revision(), revision);
+        let revision = editor.revision();
+        editor.insert_text("a");
+        editor.mark_saved(PathBuf::from("a.glicol"), editor.text());
+        assert!(!editor.is_dirty());
+        assert!(editor.revision() > revision + 1);
+    }
+
+    #[test]
+    fn reloading_keeps_cursors_around_the_change() {
+        let mut editor = editor_at("~a: sin 440\nout: ~a >> mul 0.5", 27);
+        editor.mark_saved(PathBuf::from("a.glicol"), editor.text());
+        assert!(!editor.reload(editor.text()));
+        assert!(editor.reload("~a: saw 220\nout: ~a >> mul 0.5".to_owned()));
+        assert_eq!(editor.cursors(), vec![27]);
+        assert!(!editor.is_dirty());
+        assert!(editor.take_effects().is_empty());
+        editor.set_selections(vec![Selection::cursor(6)]);
+        editor.reload("~a: sin 1\nout: ~a >> mul 0.5".to_owned());
+        assert_eq!(editor.cursors(), vec![6]);
+        run(&mut editor, &[Command::Undo]);
+        assert_eq!(editor.text(), "~a: saw 220\nout: ~a >> mul 0.5");
+    }
+
+    #[test]
+    fn reloading_can_evaluate() {
+        let settings = Settings {
+            evaluate_on_reload: true,
+            ..Settings::default()
+        };
+        let mut editor = Editor::new("out: sin 440", settings, Keymap::default());
+        editor.reload("out: sin 220".to_owned());
+        assert_eq!(
+            editor.take_effects(),
+            vec![Effect::Evaluate("out: sin 220".to_owned())]
+        );
+    }
+
+    #[test]
+    fn a_restore_offer_waits_for_an_answer() {
+        let mut editor = editor("out: sin 440");
+        editor.offer_restore("out: sin 220".to_owned());
+        editor.play("x<delete>").unwrap();
+        assert_eq!(editor.text(), "out: sin 440");
+        editor.play("y").unwrap();
+        assert_eq!(editor.text(), "out: sin 220");
+        assert!(editor.is_dirty());
+        assert!(!editor.is_offering_restore());
+    }
+
+    #[test]
+    fn a_restore_offer_can_be_declined() {
+        let mut editor = editor("out: sin 440");
+        editor.offer_restore("out: sin 220".to_owned());
+        editor.play("nx").unwrap();
+        assert_eq!(editor.text(), "xout: sin 440");
+    }
+
+    #[test]
+    fn indent_and_dedent() {
+        let mut editor = editor(">> mul 2");
+        run(&mut editor, &[Command::Indent]);
+        assert_eq!(editor.text(), "    >> mul 2");
+        run(&mut editor, &[Command::Dedent]);
+        assert_eq!(editor.text(), ">> mul 2");
+    }
+
+    #[test]
+    fn cut_and_paste() {
+        let mut editor = editor("sin 440");
+        editor.set_selections(vec![Selection::new(0, 4)]);
+        run(&mut editor, &[Command::Cut, Command::DocumentEnd]);
+        assert_eq!(editor.text(), "440");
+        editor.insert_text(" ");
+        run(&mut editor, &[Command::Paste]);
+        assert_eq!(editor.text(), "440 sin ");
+    }
+
+    #[test]
+    fn folded_lines_are_skipped() {
+        let mut editor = editor("~a: sin 1\n>> mul 2\n>> mul 3\nout: ~a");
+        run(&mut editor, &[Command::Fold]);
+        assert_eq!(editor.layout().folds, vec![1..3]);
+        run(&mut editor, &[Command::CursorDown]);
+        assert_eq!(editor.cursors(), vec![28]);
+        run(
+            &mut editor,
+            &[Command::CursorUp, Command::LineEnd, Command::CursorRight],
+        );
+        assert_eq!(editor.cursors(), vec![28]);
+        run(&mut editor, &[Command::CursorLeft]);
+        assert_eq!(editor.cursors(), vec![9]);
+        run(&mut editor, &[Command::Unfold]);
+        assert!(editor.layout().folds.is_empty());
+    }
+
+    #[test]
+    fn folds_follow_edits() {
+        let mut editor = editor("~a: sin 1\n>> mul 2\nout: ~a");
+        run(&mut editor, &[Command::Fold]);
+        assert_eq!(editor.layout().folds, vec![1..2]);
+        editor.set_selections(vec![Selection::cursor(0)]);
+        run(&mut editor, &[Command::LineEnd]);
+        editor.insert_text("\n>> mul 3");
+        assert_eq!(editor.layout().folds, vec![1..3]);
+        run(&mut editor, &[Command::Unfold]);
+        assert!(editor.layout().folds.is_empty());
+    }
+
+    #[test]
+    fn soft_wrap_moves_by_rows() {
+        let settings = Settings {
+            wrap: true,
+            ..Settings::default()
+        };
+        let text = "~t2: seq 33_33_ _33 33__33 _33 >> mul 0.5";
+        let mut editor = Editor::new(text, settings, Keymap::default());
+        editor.resize(PhysicalSize::new(200, 400), 1.0);
+        let layout = editor.layout();
+        let rows = layout.line_rows(editor.buffer(), editor.font(), 0);
+        assert!(rows.len() > 1);
+        run(&mut editor, &[Command::CursorDown]);
+        assert_eq!(editor.cursors(), vec![rows[1].range.start]);
+        run(&mut editor, &[Command::LineEnd]);
+        assert_eq!(editor.cursors(), vec![rows[1].text_end]);
+        run(&mut editor, &[Command::CursorUp, Command::LineStart]);
+        assert_eq!(editor.cursors(), vec![0]);
+    }
+
+    #[test]
+    fn vim_normal_mode_commands() {
+        let settings = Settings {
+            vim: true,
+            ..Settings::default()
+        };
+        let mut editor = Editor::new("sin 440\nout: ~a", settings, Keymap::default());
+        for ch in "dwjx".chars() {
+            editor.input(&WindowEvent::ReceivedCharacter(ch));
+        }
+        assert_eq!(editor.text(), "440\nut: ~a");
+    }
+
+    #[test]
+    fn vim_takes_huge_counts() {
+        let settings = Settings {
+            vim: true,
+            ..Settings::default()
+        };
+        let mut editor = Editor::new("sin 440\nout: ~a", settings, Keymap::default());
+        let keys = "99999999999999999999999lyl99999999999p";
+        for ch in keys.chars() {
+            editor.input(&WindowEvent::ReceivedCharacter(ch));
+        }
+        assert_eq!(
+            editor.buffer().line(0),
+            format!("sin 440{}", "0".repeat(10_000))
+        );
+        for ch in "99999999999999999999wgg99999999999999999999dd".chars() {
+            editor.input(&WindowEvent::ReceivedCharacter(ch));
+        }
+        assert_eq!(editor.text(), "");
+        run(&mut editor, &[Command::Undo, Command::Undo]);
+        assert_eq!(editor.text(), "sin 440\nout: ~a");
+    }
+
+    #[test]
+    fn vim_deletes_leave_the_clipboard_alone() {
+        let settings = Settings {
+            vim: true,
+            ..Settings::default()
+        };
+        let mut editor = Editor::new("sin 440", settings, Keymap::default());
+        editor.set_selections(vec![Selection::new(0, 3)]);
+        run(&mut editor, &[Command::Copy]);
+        editor.set_selections(vec![Selection::cursor(4)]);
+        editor.input(&WindowEvent::ReceivedCharacter('x'));
+        run(&mut editor, &[Command::Paste]);
+        assert_eq!(editor.text(), "sin sin40");
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Reads a set from disk.
+pub fn read_code(path: &Path) -> io::Result<String> {
+    // the editor works with `\n` only
+    std::fs::read_to_string(path).map(|code| code.replace("\r\n", "\n"))
+}
+
+/// Writes a set to disk, with `\r\n` line breaks when the file had them.
+pub fn write_code(path: &Path, code: &str) -> io::Result<()> {
+    if uses_crlf(path) {
+        std::fs::write(path, code.replace('\n', "\r\n"))
+    } else {
+        std::fs::write(path, code)
+    }
+}
+
+// going by the first line of the file, if there is one
+fn uses_crlf(path: &Path) -> bool {
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut line = vec![];
+    match io::BufReader::new(file).read_until(b'\n', &mut line) {
+        Ok(_) => line.ends_with(b"\r\n"),
+        Err(_) => false,
+    }
+}
+
+/// Notices when a file is written, including by our own saves.
+pub struct FileWatcher {
+    path: PathBuf,
+    // when the file was last written, until `take_change` reports it
+    changed: Arc<Mutex<Option<Instant>>>,
+    _watcher: RecommendedWatcher,
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> notify::Result<Self> {
+        let changed = Arc::new(Mutex::new(None));
+        let changed_clone = changed.clone();
+        let name = path.file_name().map(|name| name.to_owned());
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                let ours = event.paths.iter().any(|p| p.file_name() == name.as_deref());
+                if ours && matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    *changed_clone.lock().unwrap() = Some(Instant::now());
+                }
+            })?;
+        // vim and others save by writing a new file and renaming it over the
+        // old one, so the directory is watched rather than the file
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        Ok(Self {
+            path: path.to_owned(),
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was written since the last call. A write is only
+    /// reported once the file has been quiet for a moment, so a save that
+    /// truncates first is not read half done.
+    pub fn take_change(&self) -> bool {
+        let mut changed = self.changed.lock().unwrap();
+        match *changed {
+            Some(time) if time.elapsed() > Duration::from_millis(100) => {
+                *changed = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_keeps_the_line_breaks_of_the_file() {
+        let dir = std::env::temp_dir().join(format!("glicol-wgpu-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let crlf = dir.join("crlf.glicol");
+        std::fs::write(&crlf, "~a: sin 440\r\nout: ~a\r\n").unwrap();
+        let code = read_code(&crlf).unwrap();
+        assert_eq!(code, "~a: sin 440\nout: ~a\n");
+        write_code(&crlf, &format!("{}>> mul 0.5\n", code)).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&crlf).unwrap(),
+            "~a: sin 440\r\nout: ~a\r\n>> mul 0.5\r\n"
+        );
+        // new files get `\n`
+        let lf = dir.join("lf.glicol");
+        write_code(&lf, &code).unwrap();
+        assert_eq!(std::fs::read_to_string(&lf).unwrap(), code);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
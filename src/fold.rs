@@ -0,0 +1,145 @@
+use std::{cell::RefCell, ops::Range};
+
+use hashbrown::HashSet;
+
+use crate::TextBuffer;
+
+/// Folded chains, kept by name so a fold follows its chain through edits
+/// and undo; a chain whose header is renamed simply unfolds.
+#[derive(Clone, Debug, Default)]
+pub struct Folds {
+    names: HashSet<String>,
+    // the hidden lines at a buffer revision, until the folds change
+    hidden: RefCell<Option<(u64, Vec<Range<usize>>)>>,
+}
+
+// the name and lines of the chain at `line`, if it has `>>` lines to hide
+fn foldable_chain(buffer: &TextBuffer, line: usize) -> Option<(String, Range<usize>)> {
+    let lines = crate::chain_at(buffer, line)?;
+    if lines.len() < 2 {
+        return None;
+    }
+    let header = buffer.line(lines.start);
+    let name = crate::chain_name(crate::uncommented(&header))?.to_owned();
+    Some((name, lines))
+}
+
+impl Folds {
+    /// Whether `line` starts a chain that can be folded.
+    pub fn is_foldable(buffer: &TextBuffer, line: usize) -> bool {
+        foldable_chain(buffer, line).is_some_and(|(_, lines)| lines.start == line)
+    }
+
+    /// Folds the chain `line` is part of; false when there is none.
+    pub fn fold(&mut self, buffer: &TextBuffer, line: usize) -> bool {
+        let Some((name, _)) = foldable_chain(buffer, line) else {
+            return false;
+        };
+        self.names.insert(name);
+        self.hidden.take();
+        true
+    }
+
+    /// Unfolds the chain `line` is part of; false when it was not folded.
+    pub fn unfold(&mut self, buffer: &TextBuffer, line: usize) -> bool {
+        match foldable_chain(buffer, line) {
+            Some((name, _)) => {
+                self.hidden.take();
+                self.names.remove(&name)
+            }
+            None => false,
+        }
+    }
+
+    pub fn toggle(&mut self, buffer: &TextBuffer, line: usize) -> bool {
+        self.unfold(buffer, line) || self.fold(buffer, line)
+    }
+
+    pub fn fold_all(&mut self, buffer: &TextBuffer) {
+        self.hidden.take();
+        let mut line = 0;
+        while line < buffer.len_lines() {
+            match foldable_chain(buffer, line) {
+                Some((name, lines)) => {
+                    self.names.insert(name);
+                    line = lines.end;
+                }
+                None => line += 1,
+            }
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.names.clear();
+        self.hidden.take();
+    }
+
+    /// The hidden lines of every folded chain, in order: all of its lines
+    /// but the header.
+    pub fn hidden_lines(&self, buffer: &TextBuffer) -> Vec<Range<usize>> {
+        if self.names.is_empty() {
+            return vec![];
+        }
+        if let Some((revision, hidden)) = &*self.hidden.borrow() {
+            if *revision == buffer.revision() {
+                return hidden.clone();
+            }
+        }
+        let mut hidden = vec![];
+        let mut line = 0;
+        while line < buffer.len_lines() {
+            match foldable_chain(buffer, line) {
+                Some((name, lines)) => {
+                    if self.names.contains(&name) {
+                        hidden.push(lines.start + 1..lines.end);
+                    }
+                    line = lines.end;
+                }
+                None => line += 1,
+            }
+        }
+        *self.hidden.borrow_mut() = Some((buffer.revision(), hidden.clone()));
+        hidden
+    }
+
+    /// Forgets folds whose chain is gone or has nothing left to hide, so
+    /// they do not come back when a chain of that name shows up again.
+    pub fn retain_foldable(&mut self, buffer: &TextBuffer) {
+        if self.names.is_empty() {
+            return;
+        }
+        let mut present = HashSet::new();
+        let mut line = 0;
+        while line < buffer.len_lines() {
+            match foldable_chain(buffer, line) {
+                Some((name, lines)) => {
+                    present.insert(name);
+                    line = lines.end;
+                }
+                None => line += 1,
+            }
+        }
+        self.names.retain(|name| present.contains(name));
+        self.hidden.take();
+    }
+}
+
+/// Moves `pos` out of the hidden text of `hidden` (line ranges): forward
+/// to the start of the next visible line, or back to the end of the
+/// header when there is none or when moving backward.
+pub fn skip_hidden(
+    buffer: &TextBuffer,
+    hidden: &[Range<usize>],
+    pos: usize,
+    forward: bool,
+) -> usize {
+    let line = buffer.char_to_line(pos);
+    let Some(lines) = hidden.iter().find(|lines| lines.contains(&line)) else {
+        return pos;
+    };
+    if forward && lines.end < buffer.len_lines() {
+        buffer.line_to_char(lines.end)
+    } else {
+        buffer.line_end(lines.start - 1)
+    }
+}
@@ -0,0 +1,46 @@
+use std::ops::Range;
+
+use crate::TextBuffer;
+
+/// The lines of `buffer` that differ from the last evaluated `code`.
+///
+/// Unchanged lines are matched from the top and the bottom, everything in
+/// between counts as changed. When lines were only removed, the line where
+/// they used to be is marked.
+pub fn changed_lines(code: &str, buffer: &TextBuffer) -> Range<usize> {
+    let old: Vec<&str> = code.split('\n').collect();
+    let len = buffer.len_lines();
+
+    let mut prefix = 0;
+    while prefix < old.len() && prefix < len && buffer.line(prefix) == old[prefix] {
+        prefix += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old.len() - prefix
+        && suffix < len - prefix
+        && buffer.line(len - 1 - suffix) == old[old.len() - 1 - suffix]
+    {
+        suffix += 1;
+    }
+
+    if prefix == len - suffix && old.len() != len {
+        let line = prefix.min(len - 1);
+        line..line + 1
+    } else {
+        prefix..len - suffix
+    }
+}
+
+/// The 0-based line that an engine error message points at in `code`.
+///
+/// Parse errors carry a `line[n]` location; unknown references and samples
+/// only name the culprit, so the first uncommented line using it is picked.
+pub fn error_line(code: &str, message: &str) -> Option<usize> {
+    if let Some(rest) = message.split("line[").nth(1) {
+        let line: usize = rest.split(']').next()?.parse().ok()?;
+        return Some(line.saturating_sub(1));
+    }
+    let name = message.rsplit(' ').next()?;
+    code.split('\n')
+        .position(|line| !line.trim_start().starts_with("//") && line.contains(name))
+}
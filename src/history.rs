@@ -0,0 +1,145 @@
+use crate::{Selection, TextBuffer};
+
+/// A single replacement in the buffer: `removed` starting at `pos` was
+/// replaced by `inserted`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Edit {
+    pub pos: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    pub fn replace(buffer: &TextBuffer, range: std::ops::Range<usize>, text: &str) -> Self {
+        Self {
+            pos: range.start,
+            removed: buffer.slice(range),
+            inserted: text.to_owned(),
+        }
+    }
+
+    pub fn apply(&self, buffer: &mut TextBuffer) {
+        let removed_len = self.removed.chars().count();
+        if removed_len > 0 {
+            buffer.remove(self.pos..self.pos + removed_len);
+        }
+        if !self.inserted.is_empty() {
+            buffer.insert(self.pos, &self.inserted);
+        }
+    }
+
+    pub fn invert(&self) -> Self {
+        Self {
+            pos: self.pos,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+}
+
+/// What produced a transaction; consecutive typing or deleting is grouped
+/// into one undo step, everything else stands on its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+
+/// One undo step: the edits in the order they were applied, plus the cursor
+/// positions to restore on either side of it.
+#[derive(Clone, Debug)]
+pub struct Transaction {
+    pub kind: EditKind,
+    pub edits: Vec<Edit>,
+    pub cursors_before: Vec<Selection>,
+    pub cursors_after: Vec<Selection>,
+}
+
+impl Transaction {
+    pub fn new(kind: EditKind, cursors_before: Vec<Selection>) -> Self {
+        Self {
+            kind,
+            edits: vec![],
+            cursors_after: cursors_before.clone(),
+            cursors_before,
+        }
+    }
+
+    /// Applies `edit` to `buffer` and records it.
+    pub fn apply(&mut self, buffer: &mut TextBuffer, edit: Edit) {
+        edit.apply(buffer);
+        self.edits.push(edit);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    // set when the last transaction must not be extended, e.g. after the
+    // cursor moved or the code was evaluated
+    sealed: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, transaction: Transaction) {
+        if transaction.is_empty() {
+            return;
+        }
+        self.redo_stack.clear();
+        if !self.sealed {
+            if let Some(last) = self.undo_stack.last_mut() {
+                if last.kind == transaction.kind
+                    && last.kind != EditKind::Other
+                    && last.cursors_after == transaction.cursors_before
+                {
+                    last.edits.extend(transaction.edits);
+                    last.cursors_after = transaction.cursors_after;
+                    return;
+                }
+            }
+        }
+        self.undo_stack.push(transaction);
+        self.sealed = false;
+    }
+
+    /// Closes the current undo step so the next edit starts a new one,
+    /// e.g. undo never groups edits across a Shift+Enter.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// Reverts the last transaction and returns the cursors to restore.
+    pub fn undo(&mut self, buffer: &mut TextBuffer) -> Option<Vec<Selection>> {
+        let transaction = self.undo_stack.pop()?;
+        for edit in transaction.edits.iter().rev() {
+            edit.invert().apply(buffer);
+        }
+        let cursors = transaction.cursors_before.clone();
+        self.redo_stack.push(transaction);
+        self.sealed = true;
+        Some(cursors)
+    }
+
+    /// Re-applies the last undone transaction and returns the cursors to
+    /// restore.
+    pub fn redo(&mut self, buffer: &mut TextBuffer) -> Option<Vec<Selection>> {
+        let transaction = self.redo_stack.pop()?;
+        for edit in transaction.edits.iter() {
+            edit.apply(buffer);
+        }
+        let cursors = transaction.cursors_after.clone();
+        self.undo_stack.push(transaction);
+        self.sealed = true;
+        Some(cursors)
+    }
+}
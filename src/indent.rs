@@ -0,0 +1,29 @@
+use crate::{is_chain_header, TextBuffer};
+
+/// The leading whitespace of `line`.
+pub fn line_indent(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// The indentation for a line break inserted at `pos`: the current line's
+/// indentation, one level deeper right after a chain header so that the
+/// `>>` lines of the chain stand out.
+pub fn newline_indent(buffer: &TextBuffer, pos: usize, width: usize) -> String {
+    let line = buffer.line(buffer.char_to_line(pos));
+    let indent = line_indent(&line);
+    if is_chain_header(&line) {
+        format!("{}{}", indent, " ".repeat(width))
+    } else {
+        indent.to_owned()
+    }
+}
+
+/// How many leading chars of `line` one Shift+Tab removes: a tab, or up to
+/// `width` spaces.
+pub fn dedent_len(line: &str, width: usize) -> usize {
+    if line.starts_with('\t') {
+        1
+    } else {
+        line.chars().take(width).take_while(|ch| *ch == ' ').count()
+    }
+}
@@ -0,0 +1,420 @@
+use hashbrown::{HashMap, HashSet};
+use winit::event::VirtualKeyCode;
+
+use crate::{is_alt_pressed, is_ctrl_pressed, is_shift_pressed};
+
+/// A named editor command that a key chord can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Command {
+    Evaluate,
+    Panic,
+    Save,
+    SaveAs,
+    Open,
+    NewBuffer,
+    CloseBuffer,
+    NextBuffer,
+    PreviousBuffer,
+    ToggleEngine,
+    SplitRight,
+    SplitDown,
+    ClosePane,
+    FocusLeft,
+    FocusRight,
+    FocusUp,
+    FocusDown,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    AddCursorAbove,
+    AddCursorBelow,
+    SelectNextOccurrence,
+    SingleCursor,
+    ToggleComment,
+    ToggleChainComment,
+    MuteChain,
+    Indent,
+    Dedent,
+    Find,
+    Replace,
+    ToggleWrap,
+    Fold,
+    Unfold,
+    ToggleFold,
+    FoldAll,
+    UnfoldAll,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    WordLeft,
+    WordRight,
+    LineStart,
+    LineEnd,
+    DocumentStart,
+    DocumentEnd,
+    PageUp,
+    PageDown,
+    DeleteBackward,
+    DeleteForward,
+    DeleteWordBackward,
+    DeleteWordForward,
+}
+
+const COMMAND_NAMES: [(Command, &str); 55] = [
+    (Command::Evaluate, "evaluate"),
+    (Command::Panic, "panic"),
+    (Command::Save, "save"),
+    (Command::SaveAs, "save-as"),
+    (Command::Open, "open"),
+    (Command::NewBuffer, "new-buffer"),
+    (Command::CloseBuffer, "close-buffer"),
+    (Command::NextBuffer, "next-buffer"),
+    (Command::PreviousBuffer, "previous-buffer"),
+    (Command::ToggleEngine, "toggle-engine"),
+    (Command::SplitRight, "split-right"),
+    (Command::SplitDown, "split-down"),
+    (Command::ClosePane, "close-pane"),
+    (Command::FocusLeft, "focus-left"),
+    (Command::FocusRight, "focus-right"),
+    (Command::FocusUp, "focus-up"),
+    (Command::FocusDown, "focus-down"),
+    (Command::Undo, "undo"),
+    (Command::Redo, "redo"),
+    (Command::Copy, "copy"),
+    (Command::Cut, "cut"),
+    (Command::Paste, "paste"),
+    (Command::AddCursorAbove, "add-cursor-above"),
+    (Command::AddCursorBelow, "add-cursor-below"),
+    (Command::SelectNextOccurrence, "select-next-occurrence"),
+    (Command::SingleCursor, "single-cursor"),
+    (Command::ToggleComment, "toggle-comment"),
+    (Command::ToggleChainComment, "toggle-chain-comment"),
+    (Command::MuteChain, "mute-chain"),
+    (Command::Indent, "indent"),
+    (Command::Dedent, "dedent"),
+    (Command::Find, "find"),
+    (Command::Replace, "replace"),
+    (Command::ToggleWrap, "toggle-wrap"),
+    (Command::Fold, "fold"),
+    (Command::Unfold, "unfold"),
+    (Command::ToggleFold, "toggle-fold"),
+    (Command::FoldAll, "fold-all"),
+    (Command::UnfoldAll, "unfold-all"),
+    (Command::CursorLeft, "cursor-left"),
+    (Command::CursorRight, "cursor-right"),
+    (Command::CursorUp, "cursor-up"),
+    (Command::CursorDown, "cursor-down"),
+    (Command::WordLeft, "word-left"),
+    (Command::WordRight, "word-right"),
+    (Command::LineStart, "line-start"),
+    (Command::LineEnd, "line-end"),
+    (Command::DocumentStart, "document-start"),
+    (Command::DocumentEnd, "document-end"),
+    (Command::PageUp, "page-up"),
+    (Command::PageDown, "page-down"),
+    (Command::DeleteBackward, "delete-backward"),
+    (Command::DeleteForward, "delete-forward"),
+    (Command::DeleteWordBackward, "delete-word-backward"),
+    (Command::DeleteWordForward, "delete-word-forward"),
+];
+
+const DEFAULT_BINDINGS: [(&str, Command); 55] = [
+    ("shift+enter", Command::Evaluate),
+    ("ctrl+.", Command::Panic),
+    ("ctrl+s", Command::Save),
+    ("ctrl+shift+s", Command::SaveAs),
+    ("ctrl+o", Command::Open),
+    ("ctrl+n", Command::NewBuffer),
+    ("ctrl+w", Command::CloseBuffer),
+    ("ctrl+tab", Command::NextBuffer),
+    ("ctrl+shift+tab", Command::PreviousBuffer),
+    ("ctrl+e", Command::ToggleEngine),
+    ("ctrl+\\", Command::SplitRight),
+    ("ctrl+shift+\\", Command::SplitDown),
+    ("ctrl+shift+w", Command::ClosePane),
+    ("alt+left", Command::FocusLeft),
+    ("alt+right", Command::FocusRight),
+    ("alt+up", Command::FocusUp),
+    ("alt+down", Command::FocusDown),
+    ("ctrl+z", Command::Undo),
+    ("ctrl+shift+z", Command::Redo),
+    ("ctrl+y", Command::Redo),
+    ("ctrl+c", Command::Copy),
+    ("ctrl+x", Command::Cut),
+    ("ctrl+v", Command::Paste),
+    ("ctrl+alt+up", Command::AddCursorAbove),
+    ("ctrl+alt+down", Command::AddCursorBelow),
+    ("ctrl+d", Command::SelectNextOccurrence),
+    ("escape", Command::SingleCursor),
+    ("ctrl+/", Command::ToggleComment),
+    ("ctrl+shift+/", Command::ToggleChainComment),
+    ("ctrl+m", Command::MuteChain),
+    ("tab", Command::Indent),
+    ("shift+tab", Command::Dedent),
+    ("ctrl+f", Command::Find),
+    ("ctrl+h", Command::Replace),
+    ("alt+z", Command::ToggleWrap),
+    ("ctrl+shift+[", Command::Fold),
+    ("ctrl+shift+]", Command::Unfold),
+    ("ctrl+alt+[", Command::FoldAll),
+    ("ctrl+alt+]", Command::UnfoldAll),
+    ("left", Command::CursorLeft),
+    ("right", Command::CursorRight),
+    ("up", Command::CursorUp),
+    ("down", Command::CursorDown),
+    ("ctrl+left", Command::WordLeft),
+    ("ctrl+right", Command::WordRight),
+    ("home", Command::LineStart),
+    ("end", Command::LineEnd),
+    ("ctrl+home", Command::DocumentStart),
+    ("ctrl+end", Command::DocumentEnd),
+    ("pageup", Command::PageUp),
+    ("pagedown", Command::PageDown),
+    ("backspace", Command::DeleteBackward),
+    ("delete", Command::DeleteForward),
+    ("ctrl+backspace", Command::DeleteWordBackward),
+    ("ctrl+delete", Command::DeleteWordForward),
+];
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        COMMAND_NAMES.iter().find(|(c, _)| *c == self).unwrap().1
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        COMMAND_NAMES
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(c, _)| *c)
+    }
+
+    /// Commands that move the cursors; with shift held they extend the
+    /// selections instead.
+    pub fn is_navigation(self) -> bool {
+        matches!(
+            self,
+            Command::CursorLeft
+                | Command::CursorRight
+                | Command::CursorUp
+                | Command::CursorDown
+                | Command::WordLeft
+                | Command::WordRight
+                | Command::LineStart
+                | Command::LineEnd
+                | Command::DocumentStart
+                | Command::DocumentEnd
+                | Command::PageUp
+                | Command::PageDown
+        )
+    }
+}
+
+/// A key together with the modifiers held, e.g. `ctrl+shift+z`. Ctrl also
+/// stands for Cmd on macOS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key: VirtualKeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Chord {
+    pub fn new(key: VirtualKeyCode, modifiers: &HashSet<VirtualKeyCode>) -> Self {
+        Self {
+            key,
+            ctrl: is_ctrl_pressed(modifiers),
+            shift: is_shift_pressed(modifiers),
+            alt: is_alt_pressed(modifiers),
+        }
+    }
+
+    /// Parses `"ctrl+shift+z"` style chords; modifiers come in any order
+    /// and `cmd` is the same as `ctrl`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        // `ctrl++` binds the plus key
+        let (modifiers, key) = match text.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None => match text.rsplit_once('+') {
+                Some((modifiers, key)) => (modifiers, key),
+                None => ("", text.as_str()),
+            },
+        };
+        let mut chord = Self {
+            key: key_from_name(key)?,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier {
+                "ctrl" | "control" | "cmd" | "super" => chord.ctrl = true,
+                "shift" => chord.shift = true,
+                "alt" | "option" => chord.alt = true,
+                _ => return None,
+            }
+        }
+        Some(chord)
+    }
+}
+
+fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] =
+        [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const F_KEYS: [VirtualKeyCode; 12] = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        if ch.is_ascii_lowercase() {
+            return Some(LETTERS[(ch as u8 - b'a') as usize]);
+        }
+        if ch.is_ascii_digit() {
+            return Some(DIGITS[(ch as u8 - b'0') as usize]);
+        }
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<usize>().ok()) {
+        return F_KEYS.get(n.checked_sub(1)?).copied();
+    }
+    Some(match name {
+        "enter" | "return" => Return,
+        "tab" => Tab,
+        "escape" | "esc" => Escape,
+        "backspace" => Back,
+        "delete" | "del" => Delete,
+        "insert" => Insert,
+        "home" => Home,
+        "end" => End,
+        "pageup" => PageUp,
+        "pagedown" => PageDown,
+        "left" => Left,
+        "right" => Right,
+        "up" => Up,
+        "down" => Down,
+        "space" => Space,
+        "/" | "slash" => Slash,
+        "\\" | "backslash" => Backslash,
+        "." | "period" => Period,
+        "," | "comma" => Comma,
+        ";" | "semicolon" => Semicolon,
+        "'" | "apostrophe" => Apostrophe,
+        "`" | "grave" => Grave,
+        "-" | "minus" => Minus,
+        "=" | "equals" => Equals,
+        "+" | "plus" => Plus,
+        "[" => LBracket,
+        "]" => RBracket,
+        _ => return None,
+    })
+}
+
+/// Key chords mapped to commands.
+///
+/// Starts from the default bindings; a TOML file can add or replace
+/// bindings in its `[keys]` table, and bind a chord to `"none"` to free it:
+///
+/// ```toml
+/// [keys]
+/// "ctrl+enter" = "evaluate"
+/// "ctrl+m" = "toggle-chain-comment"
+/// "ctrl+d" = "none"
+/// ```
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<Chord, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .map(|(chord, command)| (Chord::parse(chord).unwrap(), *command))
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The defaults with the bindings of `text` on top. Unknown chords and
+    /// commands are errors, so a typo does not silently do nothing.
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let document: toml_edit::Document = text.parse().map_err(|e| format!("{}", e))?;
+        let mut keymap = Self::default();
+        let Some(keys) = document.get("keys") else {
+            return Ok(keymap);
+        };
+        let keys = keys.as_table_like().ok_or("`keys` is not a table")?;
+        for (chord_text, value) in keys.iter() {
+            let chord =
+                Chord::parse(chord_text).ok_or_else(|| format!("unknown key `{}`", chord_text))?;
+            let name = value
+                .as_str()
+                .ok_or_else(|| format!("`{}` must be bound to a command name", chord_text))?;
+            if name == "none" {
+                keymap.bindings.remove(&chord);
+                continue;
+            }
+            let command =
+                Command::from_name(name).ok_or_else(|| format!("unknown command `{}`", name))?;
+            keymap.bindings.insert(chord, command);
+        }
+        Ok(keymap)
+    }
+
+    /// Reads the keymap file named by `GLICOL_WGPU_KEYMAP`, or
+    /// `glicol-wgpu/keymap.toml` in the user config directory. Falls back to
+    /// the defaults when there is no file or it cannot be used.
+    pub fn load() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = keymap_path() {
+            match std::fs::read_to_string(&path) {
+                Ok(text) => match Self::from_toml(&text) {
+                    Ok(keymap) => return keymap,
+                    Err(e) => tracing::warn!("ignoring keymap {}: {}", path.display(), e),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("cannot read keymap {}: {}", path.display(), e),
+            }
+        }
+        Self::default()
+    }
+
+    /// The command for `chord`, and whether it should extend selections.
+    /// A chord with shift that is not bound falls back to the same chord
+    /// without shift, so `shift+home` selects to the line start.
+    pub fn lookup(&self, chord: Chord) -> Option<(Command, bool)> {
+        if let Some(command) = self.bindings.get(&chord) {
+            return Some((*command, false));
+        }
+        if !chord.shift {
+            return None;
+        }
+        let unshifted = Chord {
+            shift: false,
+            ..chord
+        };
+        let command = *self.bindings.get(&unshifted)?;
+        Some((command, command.is_navigation()))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn keymap_path() -> Option<std::path::PathBuf> {
+    if let Some(path) = std::env::var_os("GLICOL_WGPU_KEYMAP") {
+        return Some(path.into());
+    }
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(std::path::PathBuf::from))
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config"))
+        })?;
+    Some(config.join("glicol-wgpu").join("keymap.toml"))
+}
@@ -0,0 +1,326 @@
+use std::ops::Range;
+
+use fontdue::Font;
+
+use crate::TextBuffer;
+
+/// One row on screen: a whole line, or a piece of one when soft wrap is on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisualRow {
+    pub line: usize,
+    /// the chars drawn on this row; the last row of a line also owns its
+    /// line break, so the rows cover every char that is not folded away
+    pub range: Range<usize>,
+    /// the last place a caret can go on this row: the line end, or before
+    /// the last char when the line wraps onto the next row
+    pub text_end: usize,
+    /// clip-space x offset of the first char from the left edge
+    pub x: f32,
+    /// lines folded away after this row
+    pub folded: usize,
+}
+
+/// Where text goes on screen, in clip space (-1..1 on both axes).
+///
+/// `update_renderer` lays glyphs out with these numbers and mouse hit-testing
+/// inverts them, so the two always agree.
+pub struct TextLayout {
+    pub width: f32,
+    pub height: f32,
+    pub font_size: f32,
+    /// clip-space height of one glyph size, i.e. the baseline of the first
+    /// line sits this far below the top edge
+    pub font_size_scale: f32,
+    pub line_height: f32,
+    /// width of the line-number gutter at the left edge
+    pub gutter_width: f32,
+    /// x offset of the first column from the left edge, past the gutter
+    pub origin_x: f32,
+    /// offset from a baseline to the middle of its line band
+    pub band_center: f32,
+    /// the line shown at the top of the window
+    pub first_line: usize,
+    /// rows taken by panels at the bottom of the window, e.g. find
+    pub status_lines: usize,
+    /// break lines that do not fit the window onto more rows
+    pub wrap: bool,
+    /// extra spaces in front of wrapped continuation rows
+    pub wrap_indent: usize,
+    /// lines hidden by folds, in order
+    pub folds: Vec<Range<usize>>,
+}
+
+impl TextLayout {
+    pub fn new(
+        size: winit::dpi::PhysicalSize<u32>,
+        scale_factor: f64,
+        font: &Font,
+        first_line: usize,
+        line_count: usize,
+    ) -> Self {
+        let scale_factor = scale_factor as f32;
+        let width = size.width as f32;
+        let height = size.height as f32;
+        let font_size = 32.0 * scale_factor;
+        let line_height = font.metrics('\n', font_size).height as f32 / height * scale_factor;
+        // Get metrics for a capital letter to approximate ascent
+        let capital_metrics = font.metrics('H', font_size);
+        // Get metrics for a character with a descender to approximate descent
+        let descender_metrics = font.metrics('g', font_size);
+        // the line band is centred on the glyphs
+        let band_center =
+            (capital_metrics.height as f32 + descender_metrics.ymin as f32) / 2.0 / height;
+        // a marker column, the digits of the largest line number and a space
+        let digits = line_count.to_string().len().max(2);
+        let gutter_width = (digits + 2) as f32 * font.metrics('0', font_size).advance_width / width;
+        Self {
+            width,
+            height,
+            font_size,
+            font_size_scale: font_size / height,
+            line_height,
+            gutter_width,
+            origin_x: gutter_width + 32.0 * scale_factor / width,
+            band_center,
+            first_line,
+            status_lines: 0,
+            wrap: false,
+            wrap_indent: 4,
+            folds: vec![],
+        }
+    }
+
+    /// How many rows fit in the window, counting a partly visible last one
+    /// unless a panel covers the bottom.
+    pub fn visible_lines(&self) -> usize {
+        if self.status_lines == 0 {
+            return (((2.0 - self.font_size_scale) / self.line_height) as usize + 1).max(1);
+        }
+        // rows whose band ends above the top of the panel
+        let space = 2.0 - self.font_size_scale + self.band_center
+            - self.line_height * (self.status_lines as f32 + 0.5);
+        ((space / self.line_height).max(0.0) as usize + 1).max(1)
+    }
+
+    /// The `line_shift` that puts row `row` of the bottom panel in place,
+    /// in the same units `update_renderer` uses for text rows.
+    pub fn status_shift(&self, row: usize) -> f32 {
+        let bottom = 2.0 - self.font_size_scale + self.band_center - self.line_height / 2.0;
+        bottom - (self.status_lines - 1 - row) as f32 * self.line_height
+    }
+
+    /// One line height in physical pixels.
+    pub fn line_height_px(&self) -> f64 {
+        (self.line_height * self.height / 2.0) as f64
+    }
+
+    /// Physical pixels from the top edge to the bottom of the band of row
+    /// `rows - 1`, i.e. how tall a strip showing `rows` rows is.
+    pub fn rows_height_px(&self, rows: usize) -> f32 {
+        (self.font_size_scale - self.band_center + self.line_height * (rows as f32 - 0.5))
+            * self.height
+            / 2.0
+    }
+
+    /// The x range of every label of a tab bar, side by side from the left
+    /// edge, with the width of a digit on either side of each.
+    pub fn tab_spans(&self, font: &Font, labels: &[String]) -> Vec<Range<f32>> {
+        let padding = self.advance(font, '0');
+        let mut x = 0.0;
+        labels
+            .iter()
+            .map(|label| {
+                let start = x;
+                x += label.chars().map(|ch| self.advance(font, ch)).sum::<f32>() + 2.0 * padding;
+                start..x
+            })
+            .collect()
+    }
+
+    /// clip-space y of the baseline of visual line `row`
+    pub fn baseline(&self, row: usize) -> f32 {
+        1.0 - self.font_size_scale - row as f32 * self.line_height
+    }
+
+    /// `line`, or the header of the fold that hides it.
+    pub fn visible_line(&self, line: usize) -> usize {
+        match self.folds.iter().find(|lines| lines.contains(&line)) {
+            Some(lines) => lines.start - 1,
+            None => line,
+        }
+    }
+
+    /// The first shown line after `line`.
+    pub fn next_visible_line(&self, buffer: &TextBuffer, line: usize) -> Option<usize> {
+        let mut next = line + 1;
+        if let Some(lines) = self.folds.iter().find(|lines| lines.start == next) {
+            next = lines.end;
+        }
+        (next < buffer.len_lines()).then_some(next)
+    }
+
+    /// The last shown line before `line`.
+    pub fn prev_visible_line(&self, line: usize) -> Option<usize> {
+        Some(self.visible_line(line.checked_sub(1)?))
+    }
+
+    fn advance(&self, font: &Font, ch: char) -> f32 {
+        font.metrics(ch, self.font_size).advance_width / self.width
+    }
+
+    /// The rows `line` takes up on screen, split greedily after the last
+    /// space that still fits, or anywhere in a long word.
+    pub fn line_rows(&self, buffer: &TextBuffer, font: &Font, line: usize) -> Vec<VisualRow> {
+        let start = buffer.line_to_char(line);
+        let line_end = buffer.line_end(line);
+        let next = if line + 1 < buffer.len_lines() {
+            buffer.line_to_char(line + 1)
+        } else {
+            buffer.len_chars()
+        };
+        // a folded chain shows its header with the line break, then the
+        // line after the fold
+        let folded = match self.folds.iter().find(|lines| lines.start == line + 1) {
+            Some(lines) => lines.len(),
+            None => 0,
+        };
+        let next = if folded > 0 { line_end + 1 } else { next };
+        let whole = VisualRow {
+            line,
+            range: start..next,
+            text_end: line_end,
+            x: self.origin_x,
+            folded,
+        };
+        if !self.wrap {
+            return vec![whole];
+        }
+
+        let space = self.advance(font, ' ');
+        let right = 2.0 - space;
+        let indent_end = buffer.line_indent_end(line);
+        // continuation rows start one level past the indentation of the
+        // line, but never past the middle of the text area
+        let indent_x = (self.origin_x + (indent_end - start + self.wrap_indent) as f32 * space)
+            .min(self.origin_x + (2.0 - self.origin_x) / 2.0);
+
+        let mut rows = vec![];
+        let mut row_start = start;
+        let mut row_x = self.origin_x;
+        let mut x = self.origin_x;
+        // just after the last space on the current row
+        let mut break_at = None;
+        for (i, ch) in (start..line_end).zip(buffer.chars_at(start)) {
+            let advance = self.advance(font, ch);
+            // spaces may hang past the edge, a wrap never starts with one
+            if x + advance > right && i > row_start && ch != ' ' {
+                let wrap = break_at.unwrap_or(i);
+                rows.push(VisualRow {
+                    line,
+                    range: row_start..wrap,
+                    text_end: wrap - 1,
+                    x: row_x,
+                    folded: 0,
+                });
+                row_start = wrap;
+                row_x = indent_x;
+                x = indent_x
+                    + buffer
+                        .chars_at(wrap)
+                        .take(i - wrap)
+                        .map(|ch| self.advance(font, ch))
+                        .sum::<f32>();
+                break_at = None;
+            }
+            x += advance;
+            if ch == ' ' && i >= indent_end {
+                break_at = Some(i + 1);
+            }
+        }
+        rows.push(VisualRow {
+            range: row_start..next,
+            x: row_x,
+            ..whole
+        });
+        rows
+    }
+
+    /// The rows of `pos`'s line, and the index of the row `pos` is drawn on.
+    pub fn rows_at(&self, buffer: &TextBuffer, font: &Font, pos: usize) -> (Vec<VisualRow>, usize) {
+        let rows = self.line_rows(buffer, font, buffer.char_to_line(pos));
+        let index = rows
+            .iter()
+            .rposition(|row| row.range.start <= pos)
+            .unwrap_or(0);
+        (rows, index)
+    }
+
+    /// Up to `count` rows from the top of `first_line` on.
+    pub fn rows_from(
+        &self,
+        buffer: &TextBuffer,
+        font: &Font,
+        first_line: usize,
+        count: usize,
+    ) -> Vec<VisualRow> {
+        let mut rows = vec![];
+        let mut line = Some(self.visible_line(first_line));
+        while let Some(current) = line.filter(|_| rows.len() < count) {
+            rows.extend(self.line_rows(buffer, font, current));
+            line = self.next_visible_line(buffer, current);
+        }
+        rows.truncate(count);
+        rows
+    }
+
+    /// The rows inside the window.
+    pub fn visible_rows(&self, buffer: &TextBuffer, font: &Font) -> Vec<VisualRow> {
+        self.rows_from(buffer, font, self.first_line, self.visible_lines())
+    }
+
+    /// clip-space x of the caret at `pos` on `row`
+    pub fn row_x(&self, buffer: &TextBuffer, font: &Font, row: &VisualRow, pos: usize) -> f32 {
+        row.x
+            + buffer
+                .chars_at(row.range.start)
+                .take(pos.saturating_sub(row.range.start))
+                .map(|ch| self.advance(font, ch))
+                .sum::<f32>()
+    }
+
+    /// The caret position on `row` closest to clip-space x `x`: walk the
+    /// row with the same advances the renderer uses and stop at the glyph
+    /// whose middle is past it.
+    pub fn row_hit(&self, buffer: &TextBuffer, font: &Font, row: &VisualRow, x: f32) -> usize {
+        let mut x_offset = row.x;
+        let start = row.range.start;
+        for (pos, ch) in (start..row.text_end).zip(buffer.chars_at(start)) {
+            let advance = self.advance(font, ch);
+            if x < x_offset + advance / 2.0 {
+                return pos;
+            }
+            x_offset += advance;
+        }
+        row.text_end
+    }
+
+    /// The char index closest to the physical pixel position `(x, y)`.
+    pub fn hit_test(&self, buffer: &TextBuffer, font: &Font, x: f64, y: f64) -> usize {
+        let clip_x = (2.0 * x / self.width as f64) as f32;
+        let clip_y = 1.0 - (2.0 * y / self.height as f64) as f32;
+
+        let row = ((self.baseline(0) + self.band_center - clip_y) / self.line_height).round();
+        let row = if row < 0.0 {
+            // rows above the window scroll up past the first visible line
+            let mut line = self.visible_line(self.first_line);
+            for _ in 0..-row as usize {
+                line = self.prev_visible_line(line).unwrap_or(line);
+            }
+            self.line_rows(buffer, font, line).remove(0)
+        } else {
+            let rows = self.rows_from(buffer, font, self.first_line, row as usize + 1);
+            rows.last().unwrap().clone()
+        };
+        self.row_hit(buffer, font, &row, clip_x)
+    }
+}
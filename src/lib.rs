@@ -14,27 +14,112 @@ pub use vertex::*;
 mod utils;
 pub use utils::*;
 
+mod buffer;
+pub use buffer::*;
+
+mod selection;
+pub use selection::*;
+
+mod history;
+pub use history::*;
+
+mod settings;
+pub use settings::*;
+
+mod chain;
+pub use chain::*;
+
+mod indent;
+pub use indent::*;
+
+mod brackets;
+pub use brackets::*;
+
+mod fold;
+pub use fold::*;
+
+mod vim;
+pub use vim::*;
+
+mod search;
+pub use search::*;
+
+mod clipboard;
+pub use clipboard::*;
+
+mod gutter;
+pub use gutter::*;
+
+mod layout;
+pub use layout::*;
+
+mod editor;
+pub use editor::*;
+
+mod pane;
+pub use pane::*;
+
+mod workspace;
+pub use workspace::*;
+
 mod renderer;
 pub use renderer::*;
 
 mod input;
 use input::*;
 
+mod keymap;
+pub use keymap::*;
+
+mod script;
+pub use script::*;
+
 #[cfg(not(target_arch = "wasm32"))]
 mod audio;
 #[cfg(not(target_arch = "wasm32"))]
 pub use audio::*;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod file;
+#[cfg(not(target_arch = "wasm32"))]
+pub use file::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod recovery;
+#[cfg(not(target_arch = "wasm32"))]
+pub use recovery::*;
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
+    // `glicol-wgpu path/to/set.glicol ...` edits those files
+    #[cfg(not(target_arch = "wasm32"))]
+    let files = std::env::args_os().skip(1).map(|path| {
+        let path = std::path::PathBuf::from(path);
+        match read_code(&path) {
+            Ok(code) => (path, code),
+            // a new set, created by the first save
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (path, String::new()),
+            Err(e) => {
+                eprintln!("cannot open {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    let files = files.collect();
+    #[cfg(target_arch = "wasm32")]
+    let files = vec![];
+
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_title("glicol-wgpu")
         .build(&event_loop)
         .unwrap();
+    // composed input (e.g. pinyin, kana) arrives as `Ime::Commit`
+    window.set_ime_allowed(true);
     let window_ref = Rc::new(RefCell::new(window));
 
     #[cfg(target_arch = "wasm32")]
@@ -64,7 +149,7 @@ pub async fn run() {
             .expect("Couldn't append canvas to document body.");
     }
 
-    let mut renderer = Renderer::new(window_ref).await;
+    let mut renderer = Renderer::new(window_ref, Settings::load(), files).await;
 
     #[cfg(target_arch = "wasm32")]
     {
@@ -141,7 +226,10 @@ pub async fn run() {
             if !renderer.input(event) {
                 //modifiers
                 match event {
-                    WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                    // with unsaved changes it takes a second close
+                    WindowEvent::CloseRequested if renderer.request_close() => {
+                        *control_flow = ControlFlow::Exit
+                    }
                     // not working on web, so we manually manage modifiers
                     // #[cfg(not(target_arch = "wasm32"))]
                     // WindowEvent::ModifiersChanged(new_modifiers) => {
@@ -159,6 +247,7 @@ pub async fn run() {
             }
         }
         Event::MainEventsCleared => {
+            renderer.poll();
             renderer.window().borrow().request_redraw();
         }
         Event::RedrawRequested(window_id) if window_id == renderer.window().borrow().id() => {
@@ -0,0 +1,161 @@
+use crate::Rect;
+
+/// How the space under the tab bar is divided: a pane shows one buffer by
+/// id, a split puts two layouts side by side, or one above the other when
+/// `stacked`, each taking half.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Panes {
+    Pane(usize),
+    Split {
+        stacked: bool,
+        first: Box<Panes>,
+        second: Box<Panes>,
+    },
+}
+
+impl Panes {
+    /// The buffers shown, left to right and top to bottom.
+    pub fn ids(&self) -> Vec<usize> {
+        self.rects(Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+        })
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect()
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        self.ids().contains(&id)
+    }
+
+    /// Where every pane goes in `rect`, on whole pixels.
+    pub fn rects(&self, rect: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Panes::Pane(id) => vec![(*id, rect)],
+            Panes::Split {
+                stacked,
+                first,
+                second,
+            } => {
+                let (a, b) = if *stacked {
+                    let height = (rect.height / 2.0).floor();
+                    (
+                        Rect { height, ..rect },
+                        Rect {
+                            y: rect.y + height,
+                            height: rect.height - height,
+                            ..rect
+                        },
+                    )
+                } else {
+                    let width = (rect.width / 2.0).floor();
+                    (
+                        Rect { width, ..rect },
+                        Rect {
+                            x: rect.x + width,
+                            width: rect.width - width,
+                            ..rect
+                        },
+                    )
+                };
+                let mut rects = first.rects(a);
+                rects.extend(second.rects(b));
+                rects
+            }
+        }
+    }
+
+    /// Splits the pane of `id` in two, with `new_id` on the right or below.
+    pub fn split(&mut self, id: usize, new_id: usize, stacked: bool) -> bool {
+        match self {
+            Panes::Pane(pane) if *pane == id => {
+                *self = Panes::Split {
+                    stacked,
+                    first: Box::new(Panes::Pane(id)),
+                    second: Box::new(Panes::Pane(new_id)),
+                };
+                true
+            }
+            Panes::Pane(_) => false,
+            Panes::Split { first, second, .. } => {
+                first.split(id, new_id, stacked) || second.split(id, new_id, stacked)
+            }
+        }
+    }
+
+    /// Shows `new_id` in the pane of `id`.
+    pub fn replace(&mut self, id: usize, new_id: usize) -> bool {
+        match self {
+            Panes::Pane(pane) if *pane == id => {
+                *pane = new_id;
+                true
+            }
+            Panes::Pane(_) => false,
+            Panes::Split { first, second, .. } => {
+                first.replace(id, new_id) || second.replace(id, new_id)
+            }
+        }
+    }
+
+    /// Closes the pane of `id`, giving its space to the other half of the
+    /// split. The last pane stays.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let Panes::Split { first, second, .. } = self else {
+            return false;
+        };
+        if **first == Panes::Pane(id) {
+            *self = (**second).clone();
+            true
+        } else if **second == Panes::Pane(id) {
+            *self = (**first).clone();
+            true
+        } else {
+            first.remove(id) || second.remove(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn splits_halve_the_pane() {
+        let mut panes = Panes::Pane(0);
+        assert!(panes.split(0, 1, false));
+        assert!(panes.split(1, 2, true));
+        assert!(!panes.split(3, 4, true));
+        assert_eq!(
+            panes.rects(rect(0.0, 20.0, 801.0, 600.0)),
+            vec![
+                (0, rect(0.0, 20.0, 400.0, 600.0)),
+                (1, rect(400.0, 20.0, 401.0, 300.0)),
+                (2, rect(400.0, 320.0, 401.0, 300.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn removing_a_pane_gives_its_space_to_the_other_half() {
+        let mut panes = Panes::Pane(0);
+        panes.split(0, 1, false);
+        panes.split(1, 2, true);
+        assert!(panes.remove(0));
+        assert_eq!(panes.ids(), vec![1, 2]);
+        assert!(panes.remove(2));
+        assert_eq!(panes, Panes::Pane(1));
+        assert!(!panes.remove(1));
+    }
+}
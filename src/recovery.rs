@@ -0,0 +1,196 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hashbrown::HashMap;
+
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Copies of the unsaved buffers in a recovery directory, written every few
+/// seconds and when the app panics, so a crash on stage loses nothing.
+pub struct Recovery {
+    dir: PathBuf,
+    // the latest text of every buffer, shared with the panic hook
+    buffers: Arc<Mutex<HashMap<usize, Snapshot>>>,
+    last_autosave: Instant,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    // the editor revision and path the copy was taken at
+    revision: Option<u64>,
+    path: Option<PathBuf>,
+    // where the copy goes, and the one to remove after a save-as moved it
+    file: PathBuf,
+    stale: Option<PathBuf>,
+    code: String,
+    dirty: bool,
+    written: bool,
+}
+
+impl Recovery {
+    /// Uses the directory named by `GLICOL_WGPU_RECOVERY_DIR`, or
+    /// `glicol-wgpu/recovery` in the user state directory.
+    pub fn new() -> Option<Self> {
+        let dir = match std::env::var_os("GLICOL_WGPU_RECOVERY_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("XDG_STATE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+                .or_else(|| {
+                    std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/state"))
+                })?
+                .join("glicol-wgpu")
+                .join("recovery"),
+        };
+        Some(Self::in_dir(dir))
+    }
+
+    fn in_dir(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            buffers: Arc::new(Mutex::new(HashMap::new())),
+            last_autosave: Instant::now(),
+        }
+    }
+
+    // the copy of a file is named after its absolute path, e.g.
+    // `%home%me%sets%live.glicol`; an untitled buffer after its number
+    fn file(&self, id: usize, path: Option<&Path>) -> PathBuf {
+        let name = match path {
+            Some(path) => {
+                let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+                path.to_string_lossy().replace(['/', '\\', ':'], "%")
+            }
+            None => format!("untitled-{}.glicol", id),
+        };
+        self.dir.join(name)
+    }
+
+    /// The recovered code of buffer `id`, if its copy is newer than the
+    /// file it was editing.
+    pub fn find(&self, id: usize, path: Option<&Path>) -> Option<String> {
+        let file = self.file(id, path);
+        let copied = std::fs::metadata(&file).and_then(|m| m.modified()).ok()?;
+        let saved = path.and_then(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+        if saved.is_some_and(|saved| saved > copied) {
+            return None;
+        }
+        crate::read_code(&file).ok()
+    }
+
+    /// Notes the current text of buffer `id` if it changed since the last
+    /// time; there is something to recover while it differs from its file.
+    pub fn record(&self, id: usize, editor: &crate::Editor) {
+        let mut buffers = self.buffers.lock().unwrap();
+        let snapshot = buffers.entry(id).or_default();
+        if snapshot.revision == Some(editor.revision()) {
+            return;
+        }
+        snapshot.revision = Some(editor.revision());
+        // the path only changes on open and save-as
+        let first = snapshot.file.as_os_str().is_empty();
+        if first || snapshot.path.as_deref() != editor.path() {
+            snapshot.path = editor.path().map(Path::to_owned);
+            let file = self.file(id, editor.path());
+            if !first && snapshot.file != file {
+                snapshot.stale = Some(std::mem::replace(&mut snapshot.file, file));
+            } else {
+                snapshot.file = file;
+            }
+        }
+        snapshot.code = editor.text();
+        snapshot.dirty = editor.is_dirty();
+        snapshot.written = false;
+    }
+
+    /// Lets go of the copy of buffer `id`, closed without keeping its
+    /// changes.
+    pub fn forget(&self, id: usize) {
+        if let Some(snapshot) = self.buffers.lock().unwrap().get_mut(&id) {
+            snapshot.dirty = false;
+            snapshot.written = false;
+        }
+    }
+
+    /// Writes what changed since the last autosave, every few seconds.
+    pub fn autosave(&mut self) {
+        if self.last_autosave.elapsed() < AUTOSAVE_INTERVAL {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        self.flush();
+    }
+
+    /// Writes what changed right away, e.g. before the app quits.
+    pub fn flush(&self) {
+        flush(&self.dir, &mut self.buffers.lock().unwrap());
+    }
+
+    /// Writes every unsaved buffer before a panic unwinds, then goes on
+    /// with the default hook.
+    pub fn install_panic_hook(&self) {
+        let dir = self.dir.clone();
+        let buffers = self.buffers.clone();
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // the panic may have happened while the lock was held
+            if let Ok(mut buffers) = buffers.try_lock() {
+                flush(&dir, &mut buffers);
+            }
+            default_hook(info);
+        }));
+    }
+}
+
+// writes the copies that changed, and removes those of saved buffers
+fn flush(dir: &Path, buffers: &mut HashMap<usize, Snapshot>) {
+    if buffers.values().all(|s| s.written) {
+        return;
+    }
+    if let Err(e) = std::fs::create_dir_all(dir) {
+        tracing::warn!("cannot create {}: {}", dir.display(), e);
+        return;
+    }
+    for snapshot in buffers.values_mut().filter(|s| !s.written) {
+        if let Some(stale) = snapshot.stale.take() {
+            let _ = std::fs::remove_file(stale);
+        }
+        let result = if snapshot.dirty {
+            crate::write_code(&snapshot.file, &snapshot.code)
+        } else {
+            match std::fs::remove_file(&snapshot.file) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        };
+        match result {
+            Ok(()) => snapshot.written = true,
+            Err(e) => tracing::warn!("cannot update {}: {}", snapshot.file.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Editor, Keymap, Settings};
+
+    #[test]
+    fn forgotten_buffers_leave_no_copy() {
+        let dir = std::env::temp_dir().join(format!("glicol-wgpu-recovery-{}", std::process::id()));
+        let recovery = Recovery::in_dir(dir.clone());
+        let mut editor = Editor::new("saw 440", Settings::default(), Keymap::default());
+        editor.insert_text("~a: ");
+        recovery.record(0, &editor);
+        recovery.flush();
+        assert_eq!(recovery.find(0, None).as_deref(), Some("~a: saw 440"));
+        // a quit that discards the changes
+        recovery.forget(0);
+        recovery.flush();
+        assert_eq!(recovery.find(0, None), None);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,7 +1,6 @@
-use std::{cell::RefCell, iter, rc::Rc};
+use std::{cell::RefCell, iter, path::PathBuf, rc::Rc};
 
 // use guillotiere::{AtlasAllocator, Size};
-use hashbrown::HashSet;
 // use wgpu::{util::DeviceExt, CommandEncoderDescriptor};
 use winit::{
     event::*,
@@ -12,6 +11,8 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+use crate::{Editor, Effect, Keymap, Settings, Workspace};
+
 #[cfg(not(target_arch = "wasm32"))]
 use crate::audio::run_audio;
 
@@ -29,29 +30,52 @@ pub struct Renderer {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     // position_buffer: wgpu::Buffer,
-    num_indices: u32,
+    // the indices of every pane and the part of the window it is clipped to
+    draws: Vec<crate::Draw>,
     // position: f32,
     // #[allow(dead_code)]
     // diffuse_texture: texture::Texture,
     diffuse_bind_group: wgpu::BindGroup,
     // position_bind_group: wgpu::BindGroup,
     window: Rc<RefCell<winit::window::Window>>,
-    char_list: Vec<char>,
-    font: fontdue::Font,
+    workspace: Workspace,
+    // what the window title was last set to
+    #[cfg(not(target_arch = "wasm32"))]
+    title: String,
+    // reload the opened files when another program writes them, by buffer
+    #[cfg(not(target_arch = "wasm32"))]
+    watchers: hashbrown::HashMap<usize, crate::FileWatcher>,
+    // copies of the unsaved text, to restore after a crash
+    #[cfg(not(target_arch = "wasm32"))]
+    recovery: Option<crate::Recovery>,
     // #[cfg(target_arch = "wasm32")]
     // audio_engine: Option<Rc<RefCell<glicol::Engine<128>>>>,
     // #[cfg(target_arch = "wasm32")]
     // bpm: f32,
-    cursors: Vec<usize>,
-    modifiers: HashSet<VirtualKeyCode>,
+    // code for the audio thread by engine, see `AudioOptions`
     #[cfg(not(target_arch = "wasm32"))]
-    shared_string: std::sync::Arc<std::sync::Mutex<String>>,
+    codes: crate::EngineCodes,
     #[cfg(not(target_arch = "wasm32"))]
     has_update: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    // error message reported back by the audio thread, and the buffer that
+    // last evaluated on the shared engine, which the errors of that one go to
+    #[cfg(not(target_arch = "wasm32"))]
+    engine_error: crate::EngineError,
+    #[cfg(not(target_arch = "wasm32"))]
+    shared_evaluator: usize,
+    // set by a panic to silence the output until the next evaluation
+    #[cfg(not(target_arch = "wasm32"))]
+    muted: std::sync::Arc<std::sync::atomic::AtomicBool>,
 }
 
 impl Renderer {
-    pub async fn new(window: Rc<RefCell<winit::window::Window>>) -> Self {
+    /// `files` are paths and the code read from them, each edited in a
+    /// buffer of its own instead of the demo code.
+    pub async fn new(
+        window: Rc<RefCell<winit::window::Window>>,
+        settings: Settings,
+        files: Vec<(PathBuf, String)>,
+    ) -> Self {
         #[cfg(target_arch = "wasm32")]
         console_log::init_with_level(log::Level::Warn).expect("cannot init logger");
 
@@ -104,21 +128,64 @@ impl Renderer {
         };
         surface.configure(&device, &config);
 
-        let b = include_bytes!("FiraCode-Regular.ttf") as &[u8];
-        let font = fontdue::Font::from_bytes(b, fontdue::FontSettings::default()).unwrap();
-
-        let char_list: Vec<char> = include_str!("./code.glicol").chars().collect();
-        let cursors = vec![0];
-        let (render_pipeline, vertex_buffer, index_buffer, num_indices, diffuse_bind_group) =
+        let keymap = Keymap::load();
+        let mut editors = files.into_iter().map(|(path, code)| {
+            let mut editor = Editor::new(&code, settings.clone(), keymap.clone());
+            editor.mark_saved(path, code);
+            editor
+        });
+        let first = editors.next().unwrap_or_else(|| {
+            Editor::new(
+                include_str!("./code.glicol"),
+                settings.clone(),
+                keymap.clone(),
+            )
+        });
+        let mut workspace = Workspace::new(first);
+        for editor in editors {
+            workspace.open(editor);
+        }
+        workspace.show(0);
+        workspace.resize(size, window.borrow().scale_factor());
+        #[cfg(not(target_arch = "wasm32"))]
+        let recovery = crate::Recovery::new();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(recovery) = &recovery {
+            recovery.install_panic_hook();
+            for (id, editor) in workspace.editors_mut() {
+                offer_restore(recovery, id, editor);
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let title = workspace.editor().title();
+        #[cfg(not(target_arch = "wasm32"))]
+        window.borrow().set_title(&title);
+        let (render_pipeline, vertex_buffer, index_buffer, draws, diffuse_bind_group) =
             crate::utils::update_renderer(
-                &window, &device, &config, &queue, &char_list, &cursors, &font,
+                &device,
+                &config,
+                &queue,
+                &workspace.scenes(),
+                workspace.editor().font(),
             );
 
-        let code = String::from("");
-        let shared_string = std::sync::Arc::new(std::sync::Mutex::new(code));
-        let shared_string_clone = shared_string.clone();
+        // shared with the audio thread, which only runs on native
+        #[cfg(not(target_arch = "wasm32"))]
+        let codes: crate::EngineCodes = Default::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        let codes_clone = codes.clone();
+        #[cfg(not(target_arch = "wasm32"))]
         let has_update = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        #[cfg(not(target_arch = "wasm32"))]
         let has_update_clone = has_update.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let engine_error: crate::EngineError = Default::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        let engine_error_clone = engine_error.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        let muted = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        #[cfg(not(target_arch = "wasm32"))]
+        let muted_clone = muted.clone();
 
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -138,7 +205,12 @@ impl Renderer {
                 //     bpm,
                 //     capacity_clone,
                 // );
-                let options = (shared_string_clone, has_update_clone);
+                let options = (
+                    codes_clone,
+                    has_update_clone,
+                    engine_error_clone,
+                    muted_clone,
+                );
                 match audio_config.sample_format() {
                     cpal::SampleFormat::I8 => {
                         run_audio::<i8>(&audio_device, &audio_config.into(), options)
@@ -179,7 +251,8 @@ impl Renderer {
             });
         }
 
-        Self {
+        #[allow(unused_mut)]
+        let mut renderer = Self {
             surface,
             device,
             queue,
@@ -188,23 +261,35 @@ impl Renderer {
             render_pipeline,
             vertex_buffer,
             index_buffer,
-            num_indices,
+            draws,
             diffuse_bind_group,
             window,
             // position: 0.0,
-            char_list,
-            font,
+            workspace,
+            #[cfg(not(target_arch = "wasm32"))]
+            title,
+            #[cfg(not(target_arch = "wasm32"))]
+            watchers: hashbrown::HashMap::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            recovery,
             // #[cfg(target_arch = "wasm32")]
             // audio_engine: None,
             // #[cfg(target_arch = "wasm32")]
             // bpm: 120.,
-            cursors,
-            modifiers: HashSet::new(),
             #[cfg(not(target_arch = "wasm32"))]
-            shared_string,
+            codes,
             #[cfg(not(target_arch = "wasm32"))]
             has_update,
-        }
+            #[cfg(not(target_arch = "wasm32"))]
+            engine_error,
+            #[cfg(not(target_arch = "wasm32"))]
+            shared_evaluator: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            muted,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        renderer.watch();
+        renderer
     }
 
     pub fn window(&self) -> &Rc<RefCell<Window>> {
@@ -222,334 +307,306 @@ impl Renderer {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            let scale_factor = self.window.borrow().scale_factor();
+            self.workspace.resize(new_size, scale_factor);
 
             self.update();
         }
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        // #[cfg(target_arch = "wasm32")]
-        if self.update_code(event) {
-            return true;
-        }
-
-        if self.detect_modifiers(event) {
-            return true;
-        } else if self.move_cursor(event) {
-            self.update();
-            return true;
-        } else if self.input_or_delete_character(event) {
-            self.update();
-            return true;
-        } else {
+        if !self.workspace.input(event) {
             return false;
         }
+        self.apply_effects();
+        self.update();
+        true
     }
 
-    pub fn detect_modifiers(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                if keycode == &VirtualKeyCode::RAlt
-                    || keycode == &VirtualKeyCode::LAlt
-                    || keycode == &VirtualKeyCode::RControl
-                    || keycode == &VirtualKeyCode::LControl
-                    || keycode == &VirtualKeyCode::RShift
-                    || keycode == &VirtualKeyCode::LShift
-                    || keycode == &VirtualKeyCode::RWin
-                    || keycode == &VirtualKeyCode::LWin
-                {
-                    self.modifiers.insert(*keycode);
-                    true
-                } else {
-                    false
+    /// Whether the window may close; each buffer with unsaved changes asks
+    /// for a second close first.
+    pub fn request_close(&mut self) -> bool {
+        if self.workspace.request_close() {
+            // the unsaved changes were given up, so there is nothing to
+            // offer back on the next start
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(recovery) = &self.recovery {
+                for (id, _) in self.workspace.editors() {
+                    recovery.forget(id);
                 }
+                recovery.flush();
             }
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Released,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                if keycode == &VirtualKeyCode::RAlt
-                    || keycode == &VirtualKeyCode::LAlt
-                    || keycode == &VirtualKeyCode::RControl
-                    || keycode == &VirtualKeyCode::LControl
-                    || keycode == &VirtualKeyCode::RShift
-                    || keycode == &VirtualKeyCode::LShift
-                    || keycode == &VirtualKeyCode::RWin
-                    || keycode == &VirtualKeyCode::LWin
-                {
-                    self.modifiers.remove(keycode);
-                    true
-                } else {
-                    false
-                }
+            return true;
+        }
+        self.update();
+        false
+    }
+
+    pub fn workspace(&self) -> &Workspace {
+        &self.workspace
+    }
+
+    // passes evaluations and panics on to the audio engine, and saves to
+    // and opens from the file system
+    fn apply_effects(&mut self) {
+        for (id, effect) in self.workspace.take_effects() {
+            match effect {
+                Effect::Evaluate(code) => self.evaluate(id, code),
+                Effect::Panic => self.panic(),
+                Effect::Save(path, code) => self.save(id, path, code),
+                Effect::Open(path) => self.open(path),
+                Effect::CloseBuffer => self.close(id),
+                Effect::StopEngine => self.stop_engine(id),
+                // the workspace has switched buffers and panes already
+                Effect::NewBuffer
+                | Effect::NextBuffer
+                | Effect::PreviousBuffer
+                | Effect::SplitRight
+                | Effect::SplitDown
+                | Effect::ClosePane
+                | Effect::FocusLeft
+                | Effect::FocusRight
+                | Effect::FocusUp
+                | Effect::FocusDown => {}
             }
-            _ => false,
         }
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    fn update_code(&mut self, event: &WindowEvent) -> bool {
-        // shift + enter to play the sound based on self.char_list
-        match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                if keycode == &VirtualKeyCode::Return
-                    && (self.modifiers.contains(&VirtualKeyCode::LShift)
-                        || self.modifiers.contains(&VirtualKeyCode::RShift))
-                {
-                    let code: String = self.char_list.iter().collect();
-                    log::warn!("update code: {}", code);
-                    {
-                        let mut shared_string_lock = self.shared_string.lock().unwrap();
-                        *shared_string_lock = code;
-                    }
-                    self.has_update
-                        .store(true, std::sync::atomic::Ordering::Release);
-                    return true;
-                }
-                false
+    fn save(&mut self, id: usize, path: PathBuf, code: String) {
+        let result = crate::write_code(&path, &code);
+        let Some(editor) = self.workspace.get_mut(id) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                editor.mark_saved(path, code);
+                self.watch();
+            }
+            Err(e) => {
+                let message = format!("cannot save {}: {}", path.display(), e);
+                tracing::warn!("{}", message);
+                editor.set_notice(message);
             }
-            _ => false,
         }
     }
 
-    #[cfg(target_arch = "wasm32")]
-    fn update_code(&mut self, event: &WindowEvent) -> bool {
-        // shift + enter to play the sound based on self.char_list
-        match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        state: ElementState::Pressed,
-                        virtual_keycode: Some(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                if keycode == &VirtualKeyCode::Return
-                    && (self.modifiers.contains(&VirtualKeyCode::LShift)
-                        || self.modifiers.contains(&VirtualKeyCode::RShift))
-                {
-                    let code: String = self.char_list.iter().collect();
-                    log::warn!("update code: {}", code);
-
-                    // use the ringbuf to push
-                    // if let Some(engine) = &self.audio_engine {
-                    //     let mut engine_borrow = engine.borrow_mut();
-                    //     engine_borrow.update_with_code(&code);
-                    // }
-
-                    // call the window.run from glicol.js
-                    let window = web_sys::window().expect("no global `window` exists");
-                    let run = window
-                        .get("run")
-                        .unwrap()
-                        .dyn_into::<js_sys::Function>()
-                        .unwrap();
-                    let this = JsValue::null();
-                    run.call1(&this, &code.into()).unwrap();
-                    return true;
-                }
-                false
+    // shows the buffer of `path`, reading the file into a new one when it
+    // is not open yet; a file that does not exist is created by the first
+    // save
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open(&mut self, path: PathBuf) {
+        if self.workspace.show_path(&path) {
+            return;
+        }
+        let code = match crate::read_code(&path) {
+            Ok(code) => code,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                let message = format!("cannot open {}: {}", path.display(), e);
+                tracing::warn!("{}", message);
+                self.workspace.editor_mut().set_notice(message);
+                return;
             }
-            _ => false,
+        };
+        let shown = self.workspace.editor();
+        let mut editor = Editor::new(&code, shown.settings().clone(), shown.keymap().clone());
+        editor.mark_saved(path, code);
+        let id = self.workspace.open(editor);
+        if let Some(recovery) = &self.recovery {
+            offer_restore(recovery, id, self.workspace.editor_mut());
         }
+        self.watch();
     }
 
-    pub fn move_cursor(&mut self, event: &WindowEvent) -> bool {
-        if let WindowEvent::KeyboardInput {
-            input:
-                KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Left),
-                    ..
-                },
-            ..
-        } = event
-        {
-            // tracing::warn!("move cursor left");
-            if self.cursors[0] > 0 {
-                self.cursors[0] -= 1;
-            }
-            // tracing::warn!("cursors: {:?}", self.cursors);
-            true
-        } else if let WindowEvent::KeyboardInput {
-            input:
-                KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Right),
-                    ..
-                },
-            ..
-        } = event
-        {
-            // tracing::warn!("move cursor right");
-            if self.cursors[0] < self.char_list.len() {
-                self.cursors[0] += 1;
-            }
-            // tracing::warn!("cursors: {:?}", self.cursors);
-            true
-        } else if let WindowEvent::KeyboardInput {
-            input:
-                KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Up),
-                    ..
-                },
-            ..
-        } = event
-        {
-            // tracing::warn!("move cursor up");
-            let mut pos = self.cursors[0] as usize;
+    // lets go of the file and the engine of a closed buffer
+    #[cfg(not(target_arch = "wasm32"))]
+    fn close(&mut self, id: usize) {
+        self.watchers.remove(&id);
+        if let Some(recovery) = &self.recovery {
+            recovery.forget(id);
+        }
+        self.stop_engine(id);
+    }
 
-            // Find the start of the current line
-            while pos > 0 && self.char_list[pos - 1] != '\n' {
-                pos -= 1;
+    // follows the files being edited, which a save-as may have changed
+    #[cfg(not(target_arch = "wasm32"))]
+    fn watch(&mut self) {
+        for (id, editor) in self.workspace.editors() {
+            let Some(path) = editor.path() else {
+                continue;
+            };
+            if self.watchers.get(&id).is_some_and(|w| w.path() == path) {
+                continue;
             }
-            let current_line_pos = self.cursors[0] as usize - pos;
-
-            // If we're at the start of the text, we don't move
-            if pos == 0 {
-                return true;
+            match crate::FileWatcher::new(path) {
+                Ok(watcher) => {
+                    self.watchers.insert(id, watcher);
+                }
+                Err(e) => {
+                    tracing::warn!("cannot watch {}: {}", path.display(), e);
+                    self.watchers.remove(&id);
+                }
             }
+        }
+    }
 
-            let mut new_pos = pos - 2; // Move to the previous character from the start of the current line
-            let mut prev_line_start = 0;
-            let mut prev_line = 0;
-
-            // Find the start of the previous line
-            while new_pos > 0 {
-                if self.char_list[new_pos] == '\n' {
-                    prev_line += 1;
-                    if prev_line == 1 {
-                        prev_line_start = new_pos + 1; // After the '\n' character
-                        break;
-                    }
+    // takes in what another program wrote to the file of buffer `id`,
+    // evaluating it when `evaluate_on_reload` is set
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reload(&mut self, id: usize) {
+        let Some(editor) = self.workspace.get_mut(id) else {
+            return;
+        };
+        let Some(path) = editor.path() else {
+            return;
+        };
+        match crate::read_code(path) {
+            Ok(code) => {
+                if editor.reload(code) {
+                    self.apply_effects();
+                    self.update();
                 }
-                new_pos -= 1;
             }
+            // e.g. deleted, or moved away for a moment by the other editor
+            Err(e) => tracing::warn!("cannot reload {}: {}", path.display(), e),
+        }
+    }
 
-            let target_pos = prev_line_start + current_line_pos;
+    #[cfg(target_arch = "wasm32")]
+    fn save(&mut self, _id: usize, _path: PathBuf, _code: String) {
+        self.workspace
+            .editor_mut()
+            .set_notice("saving files needs the desktop app".to_owned());
+    }
 
-            // If the target position exceeds the start of the current line, set it to the end of the previous line
-            if target_pos >= pos {
-                self.cursors[0] = pos - 1; // Just before the current line's start
-            } else {
-                self.cursors[0] = target_pos;
-            }
+    #[cfg(target_arch = "wasm32")]
+    fn open(&mut self, _path: PathBuf) {
+        self.workspace
+            .editor_mut()
+            .set_notice("opening files needs the desktop app".to_owned());
+    }
 
-            true
-        } else if let WindowEvent::KeyboardInput {
-            input:
-                KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Down),
-                    ..
-                },
-            ..
-        } = event
-        {
-            // tracing::warn!("move cursor down");
-            let mut pos = self.cursors[0] as usize;
+    #[cfg(target_arch = "wasm32")]
+    fn close(&mut self, _id: usize) {}
 
-            // Find the start of the current line
-            while pos > 0 && self.char_list[pos - 1] != '\n' {
-                pos -= 1;
-            }
-            let current_line_pos = self.cursors[0] as usize - pos;
+    // sends the code to the engine of buffer `id`, or to the shared one
+    #[cfg(not(target_arch = "wasm32"))]
+    fn evaluate(&mut self, id: usize, code: String) {
+        let own_engine = self.workspace.get_mut(id).is_some_and(|e| e.own_engine());
+        if !own_engine {
+            self.shared_evaluator = id;
+        }
+        *self.engine_error.lock().unwrap() = None;
+        self.codes
+            .lock()
+            .unwrap()
+            .insert(own_engine.then_some(id), code);
+        self.has_update
+            .store(true, std::sync::atomic::Ordering::Release);
+        self.muted
+            .store(false, std::sync::atomic::Ordering::Release);
+    }
 
-            let mut new_pos = self.cursors[0] as usize;
-            let mut new_line = 0;
-            let mut new_line_pos = 0;
+    // the buffer goes back to the shared engine, its own one stops playing
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_engine(&mut self, id: usize) {
+        self.codes.lock().unwrap().insert(Some(id), String::new());
+        self.has_update
+            .store(true, std::sync::atomic::Ordering::Release);
+    }
 
-            while new_pos <= self.char_list.len() {
-                if new_pos == self.char_list.len() {
-                    self.cursors[0] = new_pos;
-                    break;
-                }
-                if self.char_list[new_pos] == '\n' {
-                    new_line += 1;
-                }
-                if new_line == 1 {
-                    if new_line_pos == current_line_pos {
-                        self.cursors[0] = new_pos + 1;
-                        break;
-                    }
-                    new_line_pos += 1;
-                } else if new_line == 2 {
-                    self.cursors[0] = new_pos;
-                    break;
-                }
-                new_pos += 1;
-            }
-            true
-        } else {
-            false
+    // silences the audio right away, the next evaluation brings it back
+    #[cfg(not(target_arch = "wasm32"))]
+    fn panic(&mut self) {
+        self.muted.store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn evaluate(&mut self, id: usize, code: String) {
+        // glicol.js runs a single engine
+        if let Some(editor) = self.workspace.get_mut(id).filter(|e| e.own_engine()) {
+            editor.set_notice("an engine of its own needs the desktop app".to_owned());
         }
+        // use the ringbuf to push
+        // if let Some(engine) = &self.audio_engine {
+        //     let mut engine_borrow = engine.borrow_mut();
+        //     engine_borrow.update_with_code(&code);
+        // }
+
+        // call the window.run from glicol.js
+        let window = web_sys::window().expect("no global `window` exists");
+        let run = window
+            .get("run")
+            .unwrap()
+            .dyn_into::<js_sys::Function>()
+            .unwrap();
+        let this = JsValue::null();
+        // glicol.js only logs errors, so there is no error line here
+        run.call1(&this, &code.into()).unwrap();
+        // resume the audio context in case a panic suspended it
+        call_window_function("audioStart");
     }
 
-    pub fn input_or_delete_character(
-        &mut self,
-        event: &WindowEvent,
-        // modifiers: &HashSet<VirtualKeyCode>,
-    ) -> bool {
-        if let WindowEvent::KeyboardInput {
-            input:
-                KeyboardInput {
-                    state: ElementState::Pressed,
-                    virtual_keycode: Some(VirtualKeyCode::Back),
-                    ..
-                },
-            ..
-        } = event
+    #[cfg(target_arch = "wasm32")]
+    fn panic(&mut self) {
+        call_window_function("audioStop");
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn stop_engine(&mut self, _id: usize) {}
+
+    /// Picks up what arrived outside of window events: clipboard text in
+    /// the browser, errors from the audio thread and changes to the files.
+    pub fn poll(&mut self) {
+        if self.workspace.poll() {
+            self.update();
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
         {
-            tracing::warn!("delete character");
-            if self.cursors[0] >= 1 {
-                self.char_list.remove(self.cursors[0] as usize - 1);
-                if self.cursors[0] >= 1 {
-                    self.cursors[0] -= 1;
-                }
-            } else {
-                // cursor is at the beginning
-                if self.char_list.len() > 0 {
-                    self.char_list.remove(0);
+            let error = self.engine_error.lock().unwrap().take();
+            if let Some((engine, message)) = error {
+                log::warn!("engine error: {}", message);
+                let id = engine.unwrap_or(self.shared_evaluator);
+                if let Some(editor) = self.workspace.get_mut(id) {
+                    editor.set_engine_error(&message);
+                    self.update();
                 }
             }
-            true
-        } else {
-            let c = crate::get_char_from_event(event, &self.modifiers);
-            if let Some(c) = c {
-                tracing::warn!("add character: {:?}", c);
-                self.char_list.insert(self.cursors[0] as usize, c);
-                self.cursors[0] += 1;
-                true
-            } else {
-                false
+            let changed: Vec<usize> = self
+                .watchers
+                .iter()
+                .filter(|(_, watcher)| watcher.take_change())
+                .map(|(id, _)| *id)
+                .collect();
+            for id in changed {
+                self.reload(id);
+            }
+            if let Some(recovery) = &mut self.recovery {
+                recovery.autosave();
             }
         }
     }
 
     pub fn update(&mut self) {
+        // the file name and the unsaved marker of the shown buffer
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let title = self.workspace.editor().title();
+            if title != self.title {
+                self.window.borrow().set_title(&title);
+                self.title = title;
+            }
+            // the recovered copy stays until the restore is answered
+            if let Some(recovery) = &self.recovery {
+                for (id, editor) in self.workspace.editors() {
+                    if !editor.is_offering_restore() {
+                        recovery.record(id, editor);
+                    }
+                }
+            }
+        }
         // self.queue.write_buffer(
         //     &self.position_buffer,
         //     0,
@@ -559,16 +616,14 @@ impl Renderer {
             self.render_pipeline,
             self.vertex_buffer,
             self.index_buffer,
-            self.num_indices,
+            self.draws,
             self.diffuse_bind_group,
         ) = crate::utils::update_renderer(
-            &self.window,
             &self.device,
             &self.config,
             &self.queue,
-            &self.char_list,
-            &self.cursors,
-            &self.font,
+            &self.workspace.scenes(),
+            self.workspace.editor().font(),
         );
     }
 
@@ -603,7 +658,15 @@ impl Renderer {
             // render_pass.set_bind_group(1, &self.position_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+            for (indices, rect) in &self.draws {
+                let Some([x, y, width, height]) =
+                    crate::scissor(rect, self.config.width, self.config.height)
+                else {
+                    continue;
+                };
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.draw_indexed(indices.clone(), 0, 0..1);
+            }
         }
 
         self.queue.submit(iter::once(encoder.finish()));
@@ -612,3 +675,22 @@ impl Renderer {
         Ok(())
     }
 }
+
+// offers the copy of buffer `id` saved before a crash, if it differs
+#[cfg(not(target_arch = "wasm32"))]
+fn offer_restore(recovery: &crate::Recovery, id: usize, editor: &mut Editor) {
+    if let Some(code) = recovery.find(id, editor.path()) {
+        if code != editor.text() {
+            editor.offer_restore(code);
+        }
+    }
+}
+
+// calls a function that app.js puts on `window`, if it is there
+#[cfg(target_arch = "wasm32")]
+fn call_window_function(name: &str) {
+    let window = web_sys::window().expect("no global `window` exists");
+    if let Some(Ok(function)) = window.get(name).map(|f| f.dyn_into::<js_sys::Function>()) {
+        let _ = function.call0(&JsValue::null());
+    }
+}
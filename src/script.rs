@@ -0,0 +1,160 @@
+use crate::Chord;
+use winit::event::*;
+
+/// Turns a key script into the window events a keyboard would send, so the
+/// editor can be driven without a window.
+///
+/// Plain chars are typed, pressing their key around the character like a
+/// keyboard does (on a us layout, with shift for capitals and symbols), a
+/// new line presses enter and `<chord>` presses a key with its modifiers
+/// held, written like the keymap file: `<up>`, `<shift+end>`,
+/// `<ctrl+shift+z>`. `<<` types a `<`.
+pub fn key_events(script: &str) -> Result<Vec<WindowEvent<'static>>, String> {
+    let mut events = vec![];
+    let mut chars = script.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '<' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('>') => break,
+                        Some('<') if name.is_empty() => {
+                            name.push('<');
+                            break;
+                        }
+                        Some(ch) => name.push(ch),
+                        None => return Err(format!("unclosed `<{}`", name)),
+                    }
+                }
+                if name == "<" {
+                    events.extend(char_events('<'));
+                    continue;
+                }
+                let chord =
+                    Chord::parse(&name).ok_or_else(|| format!("unknown key `<{}>`", name))?;
+                events.extend(chord_events(chord));
+            }
+            '\n' => events.extend(chord_events(Chord::parse("enter").unwrap())),
+            ch => events.extend(char_events(ch)),
+        }
+    }
+    Ok(events)
+}
+
+/// Presses and releases the key of `chord` with its modifiers held.
+pub fn chord_events(chord: Chord) -> Vec<WindowEvent<'static>> {
+    let modifiers: Vec<_> = [
+        (chord.ctrl, VirtualKeyCode::LControl),
+        (chord.shift, VirtualKeyCode::LShift),
+        (chord.alt, VirtualKeyCode::LAlt),
+    ]
+    .into_iter()
+    .filter(|(held, _)| *held)
+    .map(|(_, key)| key)
+    .collect();
+    let mut events = vec![];
+    for key in &modifiers {
+        events.push(key_event(*key, ElementState::Pressed));
+    }
+    events.push(key_event(chord.key, ElementState::Pressed));
+    // the key still types its character unless ctrl makes it a shortcut
+    if let Some(ch) = key_char(chord.key, chord.shift).filter(|_| !chord.ctrl) {
+        events.push(WindowEvent::ReceivedCharacter(ch));
+    }
+    events.push(key_event(chord.key, ElementState::Released));
+    for key in modifiers.iter().rev() {
+        events.push(key_event(*key, ElementState::Released));
+    }
+    events
+}
+
+// types `ch` with the key that has it, or sends it alone like an input
+// method does when there is none
+fn char_events(ch: char) -> Vec<WindowEvent<'static>> {
+    match char_chord(ch) {
+        Some(chord) => chord_events(chord),
+        None => vec![WindowEvent::ReceivedCharacter(ch)],
+    }
+}
+
+const LETTERS: [VirtualKeyCode; 26] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ]
+};
+
+// the other keys of a us layout that type, without and with shift
+const SYMBOLS: [(VirtualKeyCode, char, char); 23] = {
+    use VirtualKeyCode::*;
+    [
+        (Key1, '1', '!'),
+        (Key2, '2', '@'),
+        (Key3, '3', '#'),
+        (Key4, '4', '$'),
+        (Key5, '5', '%'),
+        (Key6, '6', '^'),
+        (Key7, '7', '&'),
+        (Key8, '8', '*'),
+        (Key9, '9', '('),
+        (Key0, '0', ')'),
+        (Grave, '`', '~'),
+        (Minus, '-', '_'),
+        (Equals, '=', '+'),
+        (LBracket, '[', '{'),
+        (RBracket, ']', '}'),
+        (Backslash, '\\', '|'),
+        (Semicolon, ';', ':'),
+        (Apostrophe, '\'', '"'),
+        (Comma, ',', '<'),
+        (Period, '.', '>'),
+        (Slash, '/', '?'),
+        (Space, ' ', ' '),
+        (Tab, '\t', '\t'),
+    ]
+};
+
+// the character a key types
+fn key_char(key: VirtualKeyCode, shift: bool) -> Option<char> {
+    if let Some(i) = LETTERS.iter().position(|k| *k == key) {
+        let ch = (b'a' + i as u8) as char;
+        return Some(if shift { ch.to_ascii_uppercase() } else { ch });
+    }
+    let (_, plain, shifted) = SYMBOLS.iter().find(|(k, _, _)| *k == key)?;
+    Some(if shift { *shifted } else { *plain })
+}
+
+// the key that types `ch`, with shift if it takes it
+fn char_chord(ch: char) -> Option<Chord> {
+    let (key, shift) = if ch.is_ascii_alphabetic() {
+        let i = ch.to_ascii_lowercase() as u8 - b'a';
+        (LETTERS[i as usize], ch.is_ascii_uppercase())
+    } else {
+        SYMBOLS.iter().find_map(|(key, plain, shifted)| {
+            (ch == *plain || ch == *shifted).then_some((*key, ch != *plain))
+        })?
+    };
+    Some(Chord {
+        key,
+        ctrl: false,
+        shift,
+        alt: false,
+    })
+}
+
+/// A synthetic key press or release.
+#[allow(deprecated)]
+pub fn key_event(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+    WindowEvent::KeyboardInput {
+        // safe for events that never reach the platform
+        device_id: unsafe { DeviceId::dummy() },
+        input: KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(key),
+            modifiers: ModifiersState::empty(),
+        },
+        is_synthetic: true,
+    }
+}
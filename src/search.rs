@@ -0,0 +1,181 @@
+use std::{cell::RefCell, ops::Range};
+
+use regex::Regex;
+
+use crate::{is_word_char, TextBuffer};
+
+/// Which text field of the find panel receives typing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchField {
+    #[default]
+    Query,
+    Replacement,
+}
+
+/// The state of the find / replace panel, kept while it is closed so the
+/// next Ctrl+F starts from the last query.
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pub query: String,
+    pub replacement: String,
+    /// treat the query as a regular expression, and allow `$1` style
+    /// groups in the replacement
+    pub regex: bool,
+    /// only match whole words, see `is_word_char`
+    pub whole_word: bool,
+    /// whether the replace field is shown
+    pub replacing: bool,
+    pub field: SearchField,
+    // the pattern of the last query, and the matches it had at a buffer
+    // revision, as the view asks for them on every frame
+    cache: RefCell<Cache>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Cache {
+    // query, regex and whole word
+    key: (String, bool, bool),
+    pattern: Option<Result<Regex, regex::Error>>,
+    matches: Option<(u64, Vec<Range<usize>>)>,
+}
+
+impl Search {
+    /// The text field that receives typing.
+    pub fn field_mut(&mut self) -> &mut String {
+        match self.field {
+            SearchField::Query => &mut self.query,
+            SearchField::Replacement => &mut self.replacement,
+        }
+    }
+
+    // compiled again only when the query or its options change
+    fn pattern(&self) -> Result<Regex, regex::Error> {
+        let mut cache = self.cache.borrow_mut();
+        let (query, regex, whole_word) = &cache.key;
+        if cache.pattern.is_none()
+            || *query != self.query
+            || *regex != self.regex
+            || *whole_word != self.whole_word
+        {
+            *cache = Cache {
+                key: (self.query.clone(), self.regex, self.whole_word),
+                pattern: Some(self.compile()),
+                matches: None,
+            };
+        }
+        cache.pattern.clone().unwrap()
+    }
+
+    fn compile(&self) -> Result<Regex, regex::Error> {
+        let query = if self.regex {
+            format!("(?:{})", self.query)
+        } else {
+            regex::escape(&self.query)
+        };
+        if !self.whole_word {
+            return Regex::new(&query);
+        }
+        // no word char may touch the match: `\b` says so next to a word
+        // char, `\B` next to another char like the `~` of `~a`
+        let edge = |ch: Option<char>| {
+            if self.regex || ch.is_some_and(is_word_char) {
+                r"\b"
+            } else {
+                r"\B"
+            }
+        };
+        let (first, last) = (self.query.chars().next(), self.query.chars().last());
+        Regex::new(&format!("{}{}{}", edge(first), query, edge(last)))
+    }
+
+    /// Every non-empty match in `buffer` as char ranges, in order. Fails
+    /// when the query is not a valid regular expression.
+    pub fn matches(&self, buffer: &TextBuffer) -> Result<Vec<Range<usize>>, regex::Error> {
+        if self.query.is_empty() {
+            return Ok(vec![]);
+        }
+        let pattern = self.pattern()?;
+        if let Some((revision, matches)) = &self.cache.borrow().matches {
+            if *revision == buffer.revision() {
+                return Ok(matches.clone());
+            }
+        }
+        let text = buffer.to_string();
+        let matches: Vec<_> = pattern
+            .find_iter(&text)
+            .filter(|m| !m.is_empty())
+            .map(|m| buffer.byte_to_char(m.start())..buffer.byte_to_char(m.end()))
+            .collect();
+        self.cache.borrow_mut().matches = Some((buffer.revision(), matches.clone()));
+        Ok(matches)
+    }
+
+    /// Every match with the text that replaces it.
+    pub fn replacements(
+        &self,
+        buffer: &TextBuffer,
+    ) -> Result<Vec<(Range<usize>, String)>, regex::Error> {
+        if self.query.is_empty() {
+            return Ok(vec![]);
+        }
+        let pattern = self.pattern()?;
+        let text = buffer.to_string();
+        let mut found = vec![];
+        for captures in pattern.captures_iter(&text) {
+            let m = captures.get(0).unwrap();
+            if m.is_empty() {
+                continue;
+            }
+            let range = buffer.byte_to_char(m.start())..buffer.byte_to_char(m.end());
+            let replacement = if self.regex {
+                let mut expanded = String::new();
+                captures.expand(&self.replacement, &mut expanded);
+                expanded
+            } else {
+                self.replacement.clone()
+            };
+            found.push((range, replacement));
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str, regex: bool, whole_word: bool) -> Search {
+        Search {
+            query: query.to_owned(),
+            regex,
+            whole_word,
+            ..Search::default()
+        }
+    }
+
+    #[test]
+    fn whole_words_are_found_next_to_rejected_matches() {
+        let buffer = TextBuffer::from("xa a a ~a b~a");
+        let matches = |s: &Search| s.matches(&buffer).unwrap();
+        assert_eq!(matches(&search("a a", false, true)), vec![3..6]);
+        assert_eq!(matches(&search("~a", false, true)), vec![7..9]);
+        assert_eq!(
+            matches(&search("a+", true, true)),
+            vec![3..4, 5..6, 8..9, 12..13]
+        );
+    }
+
+    #[test]
+    fn matches_follow_edits_and_queries() {
+        let mut buffer = TextBuffer::from("sin 440");
+        let mut search = search("440", false, false);
+        assert_eq!(search.matches(&buffer).unwrap(), vec![4..7]);
+        buffer.insert(0, "440 ");
+        assert_eq!(search.matches(&buffer).unwrap(), vec![0..3, 8..11]);
+        search.query = "sin".to_owned();
+        assert_eq!(search.matches(&buffer).unwrap(), vec![4..7]);
+        search.regex = true;
+        search.query = "(".to_owned();
+        assert!(search.matches(&buffer).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+/// A selection between `anchor` and `head`; the caret is drawn at `head`.
+/// When both are equal it is a plain cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn cursor(pos: usize) -> Self {
+        Self {
+            anchor: pos,
+            head: pos,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn start(&self) -> usize {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> usize {
+        self.anchor.max(self.head)
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        self.range().contains(&pos)
+    }
+
+    /// Moves the head to `pos`, keeping the anchor when `extend` is set and
+    /// collapsing to a cursor otherwise.
+    pub fn move_to(self, pos: usize, extend: bool) -> Self {
+        if extend {
+            Self::new(self.anchor, pos)
+        } else {
+            Self::cursor(pos)
+        }
+    }
+
+    /// Grows the selection towards `pos` while keeping everything it already
+    /// covers, the way a drag extends a double-clicked word.
+    pub fn span_to(self, pos: usize) -> Self {
+        if pos < self.start() {
+            Self::new(self.end(), pos)
+        } else {
+            Self::new(self.start(), pos.max(self.end()))
+        }
+    }
+}
+
+/// Sorts `selections` by position and merges the ones that overlap, or
+/// cursors that ended up on the same spot, so every edit applies once.
+pub fn merge_selections(selections: &mut Vec<Selection>) {
+    selections.sort_by_key(|s| (s.start(), s.end()));
+    let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+    for selection in selections.drain(..) {
+        if let Some(last) = merged.last_mut() {
+            let overlaps = selection.start() < last.end()
+                || (selection.start() == last.end() && (selection.is_empty() || last.is_empty()));
+            if overlaps {
+                let start = last.start();
+                let end = last.end().max(selection.end());
+                *last = if last.head < last.anchor {
+                    Selection::new(end, start)
+                } else {
+                    Selection::new(start, end)
+                };
+                continue;
+            }
+        }
+        merged.push(selection);
+    }
+    *selections = merged;
+}
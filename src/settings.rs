@@ -0,0 +1,61 @@
+/// Editor preferences that are not tied to a single buffer.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// spaces per indentation level for Tab, Shift+Tab and auto-indent
+    pub indent_width: usize,
+    /// evaluate the code right after Ctrl+/ so a muted chain drops out of
+    /// the mix at once
+    pub evaluate_on_comment: bool,
+    /// modal Vim-style editing
+    pub vim: bool,
+    /// soft-wrap long lines at the window width
+    pub wrap: bool,
+    /// evaluate the code when the opened file is changed by another program,
+    /// to use another editor and this one as the player
+    pub evaluate_on_reload: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            evaluate_on_comment: false,
+            vim: false,
+            wrap: false,
+            evaluate_on_reload: false,
+        }
+    }
+}
+
+impl Settings {
+    /// The defaults, overridden by `GLICOL_WGPU_*` environment variables on
+    /// desktop.
+    pub fn load() -> Self {
+        #[allow(unused_mut)]
+        let mut settings = Self::default();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(width) = std::env::var("GLICOL_WGPU_INDENT_WIDTH")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+        {
+            settings.indent_width = width.clamp(1, 16);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(value) = std::env::var("GLICOL_WGPU_EVALUATE_ON_COMMENT") {
+            settings.evaluate_on_comment = matches!(value.as_str(), "1" | "true");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(value) = std::env::var("GLICOL_WGPU_VIM") {
+            settings.vim = matches!(value.as_str(), "1" | "true");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(value) = std::env::var("GLICOL_WGPU_WRAP") {
+            settings.wrap = matches!(value.as_str(), "1" | "true");
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(value) = std::env::var("GLICOL_WGPU_EVALUATE_ON_RELOAD") {
+            settings.evaluate_on_reload = matches!(value.as_str(), "1" | "true");
+        }
+        settings
+    }
+}
@@ -3,11 +3,13 @@
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    @location(2) color: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
+    @location(1) color: vec4<f32>,
 }
 
 // @group(1) @binding(0)
@@ -19,6 +21,7 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
+    out.color = model.color;
     out.clip_position = vec4<f32>(model.position, 1.0);
     return out;
 }
@@ -34,12 +37,12 @@ var s_diffuse: sampler;
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     var color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
     // by default it's red, but we use this to get binary data
-    var grayscale = 1. * color.r + 1. * color.g + 1. * color.b;
-    var fontcolor = grayscale * 0.5;
+    var coverage = 1. * color.r + 1. * color.g + 1. * color.b;
     if (in.tex_coords.x == 0.0 || in.tex_coords.y == 0.0) {
-        return vec4<f32>(0.0, 0.3, 0.5, 0.9);
+        // cursors, selections and other solid rectangles
+        return in.color;
     } else {
-        return vec4<f32>(fontcolor, fontcolor, fontcolor, color.a);
+        return vec4<f32>(in.color.rgb, in.color.a * coverage);
     }
     
 }
\ No newline at end of file
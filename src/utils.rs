@@ -1,27 +1,120 @@
 use fontdue::Font;
 use guillotiere::{AtlasAllocator, Size};
-use std::{cell::RefCell, char, rc::Rc};
+use hashbrown::HashMap;
+use std::ops::Range;
 use wgpu::{util::DeviceExt, CommandEncoderDescriptor};
 
-use crate::Vertex;
+use crate::{Selection, TextBuffer, TextLayout, Vertex};
+
+const TEXT_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const CURSOR_COLOR: [f32; 4] = [0.0, 0.3, 0.5, 0.9];
+const SELECTION_COLOR: [f32; 4] = [0.2, 0.4, 0.6, 0.4];
+const GUTTER_COLOR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];
+const CHANGED_COLOR: [f32; 4] = [0.8, 0.6, 0.1, 0.9];
+const ERROR_COLOR: [f32; 4] = [0.7, 0.1, 0.1, 0.5];
+const BRACKET_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 0.8];
+const MATCH_COLOR: [f32; 4] = [0.6, 0.5, 0.1, 0.35];
+const PANEL_COLOR: [f32; 4] = [0.12, 0.12, 0.12, 1.0];
+const ACTIVE_TAB_COLOR: [f32; 4] = [0.2, 0.2, 0.2, 1.0];
+const FOCUS_COLOR: [f32; 4] = [0.0, 0.3, 0.5, 1.0];
+
+/// The editor state `update_renderer` draws.
+pub struct TextView<'a> {
+    pub buffer: &'a TextBuffer,
+    pub cursors: &'a [Selection],
+    /// lines edited since the code was last evaluated
+    pub changed_lines: Range<usize>,
+    /// the line the audio engine reported an error on
+    pub error_line: Option<usize>,
+    /// char indices of matching brackets to draw a box around
+    pub brackets: Vec<usize>,
+    /// search matches, sorted
+    pub matches: Vec<Range<usize>>,
+    /// rows of the panel at the bottom of the window, and the row and
+    /// column of its caret
+    pub status: Vec<String>,
+    pub status_cursor: Option<(usize, usize)>,
+    /// draw carets as blocks over the char, for vim normal mode
+    pub block_caret: bool,
+}
+
+/// A part of the window, in physical pixels from its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// What `update_renderer` draws into a `Rect`, laid out as if the rect were
+/// the whole window.
+pub enum Scene<'a> {
+    Text(TextView<'a>, TextLayout),
+    /// tab labels and the index of the active one
+    Tabs(Vec<String>, usize, TextLayout),
+    /// a border around a pane, lit when it has the focus
+    Frame(bool),
+}
+
+/// Indices to draw with `draw_indexed`, and the part of the window they may
+/// draw on.
+pub type Draw = (Range<u32>, Rect);
+
+/// A rectangle from `top_left` to `bottom_right` in clip space, textured with
+/// the atlas region `tex` (left, top, right, bottom). Quads with all-zero
+/// texture coordinates are filled with `color`.
+fn quad(top_left: [f32; 2], bottom_right: [f32; 2], tex: [f32; 4], color: [f32; 4]) -> [Vertex; 4] {
+    [
+        Vertex {
+            position: [top_left[0], top_left[1], 0.0],
+            tex_coords: [tex[0], tex[1]],
+            color,
+        },
+        Vertex {
+            position: [top_left[0], bottom_right[1], 0.0],
+            tex_coords: [tex[0], tex[3]],
+            color,
+        },
+        Vertex {
+            position: [bottom_right[0], bottom_right[1], 0.0],
+            tex_coords: [tex[2], tex[3]],
+            color,
+        },
+        Vertex {
+            position: [bottom_right[0], top_left[1], 0.0],
+            tex_coords: [tex[2], tex[1]],
+            color,
+        },
+    ]
+}
+
+fn push_quad(vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, quad: &[Vertex; 4]) {
+    let i = vertices.len() as u16;
+    vertices.extend_from_slice(quad);
+    indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 3, i]);
+}
 
 pub fn update_renderer(
-    window: &Rc<RefCell<winit::window::Window>>,
     device: &wgpu::Device,
     config: &wgpu::SurfaceConfiguration,
     queue: &wgpu::Queue,
-    char_list: &Vec<char>,
-    cursors: &Vec<usize>,
+    scenes: &[(Scene, Rect)],
     font: &Font,
 ) -> (
     wgpu::RenderPipeline,
     wgpu::Buffer,
     wgpu::Buffer,
-    u32,
+    Vec<Draw>,
     wgpu::BindGroup,
 ) {
     let mut allocator = AtlasAllocator::new(Size::new(2048, 2048));
-    let font_size = 32.0 * window.borrow().scale_factor() as f32;
     let padding = 10; // (10. * window.borrow().scale_factor()) as i32;
     allocator.clear();
     let texture = device.create_texture(&wgpu::TextureDescriptor {
@@ -42,133 +135,22 @@ pub fn update_renderer(
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
 
-    let mut line_shift = 0.0; // shift caused by \n character
-    let mut x_offset = 32.0 / window.borrow().inner_size().width as f32;
-    let mut bypass_count = 0; // \n is not rendered, so we need to skip it
-    let mut cursor_positions = vec![];
-    for (i, ch) in char_list.iter().enumerate() {
-        let i = i - bypass_count;
-
-        if *ch == '\n' {
-            let (metrics, _bitmap) = font.rasterize(*ch, font_size);
-            line_shift += metrics.height as f32 / window.borrow().inner_size().height as f32
-                * window.borrow().scale_factor() as f32;
-            x_offset = 32.0 / window.borrow().inner_size().width as f32;
-            bypass_count += 1; // skip the newline character
-            if i + bypass_count - 1 < char_list.len()
-                && (char_list[i + bypass_count - 1] == '\n')
-                && cursors.contains(&(i + bypass_count))
-            {
-                cursor_positions.push((
-                    x_offset,
-                    -line_shift, // metrics.ymin as f32 / window.borrow().inner_size().height as f32
-                ));
-            }
-            // edge case
-            if char_list.len() > 0 {
-                if cursors.contains(&0) && char_list[0] == '\n' {
-                    cursor_positions.push((
-                        x_offset,
-                        0.0, // metrics.ymin as f32 / window.borrow().inner_size().height as f32,
-                    ));
-                }
-            }
-            continue;
-        } else if *ch == ' ' {
-            // Handle space character
-            let (metrics, _) = font.rasterize(*ch, font_size);
-
-            let top_left_x = 0.0;
-            let top_left_y = 0.0;
-            let bottom_right_x = 0.0;
-            let bottom_right_y = 0.0;
-            let char_width = metrics.width as f32 / window.borrow().inner_size().width as f32;
-            let char_height = metrics.height as f32 / window.borrow().inner_size().height as f32;
-            let y_offset = metrics.ymin as f32 / window.borrow().inner_size().height as f32;
-            let font_size_scale = font_size / window.borrow().inner_size().height as f32;
-            if cursors.contains(&(i + bypass_count)) {
-                cursor_positions.push((
-                    x_offset,
-                    -line_shift, // metrics.ymin as f32 / window.borrow().inner_size().height as f32
-                ));
-            }
-            // edge case: cursor is in the end of the line
-            if cursors.contains(&(i + 1 + bypass_count)) {
-                cursor_positions.push((
-                    x_offset + metrics.advance_width / window.borrow().inner_size().width as f32,
-                    -line_shift, // metrics.ymin as f32 / window.borrow().inner_size().height as f32
-                ));
-            }
-            vertices.extend_from_slice(&[
-                Vertex {
-                    position: [
-                        -1.0 + x_offset,
-                        char_height + y_offset + 1.0 - font_size_scale,
-                        0.0,
-                    ],
-                    tex_coords: [top_left_x, top_left_y],
-                },
-                Vertex {
-                    position: [-1.0 + x_offset, y_offset + 1.0 - font_size_scale, 0.0],
-                    tex_coords: [top_left_x, bottom_right_y],
-                },
-                Vertex {
-                    position: [
-                        char_width + x_offset - 1.0,
-                        y_offset + 1.0 - font_size_scale,
-                        0.0,
-                    ],
-                    tex_coords: [bottom_right_x, bottom_right_y],
-                },
-                Vertex {
-                    position: [
-                        char_width + x_offset - 1.0,
-                        char_height + y_offset + 1.0 - font_size_scale,
-                        0.0,
-                    ],
-                    tex_coords: [bottom_right_x, top_left_y],
-                },
-            ]);
-            x_offset += metrics.advance_width / window.borrow().inner_size().width as f32;
-            indices.extend_from_slice(&[
-                0 + i as u16 * 4,
-                1 + i as u16 * 4,
-                2 + i as u16 * 4,
-                2 + i as u16 * 4,
-                3 + i as u16 * 4,
-                0 + i as u16 * 4,
-            ]);
-        } else {
-            let (metrics, bitmap) = font.rasterize(*ch, font_size);
-
-            // tracing::warn!("\n\n {:?}, Metrics {:?}\n\n", ch, metrics);
-            let size = Size::new(
-                metrics.width as i32 + padding * 2,
-                metrics.height as i32 + padding * 2,
-            );
-
-            let char_width = metrics.width as f32 / window.borrow().inner_size().width as f32;
-            let char_height = (metrics.height as f32) / window.borrow().inner_size().height as f32;
-            let y_offset =
-                metrics.ymin as f32 / window.borrow().inner_size().height as f32 - line_shift;
-            let font_size_scale = font_size / window.borrow().inner_size().height as f32;
-
-            if cursors.contains(&(i + bypass_count)) {
-                cursor_positions.push((
-                    x_offset,
-                    -line_shift, // metrics.ymin as f32 / window.borrow().inner_size().height as f32
-                ));
-            }
-
-            // edge case: cursor is in the end of the line
-            if cursors.contains(&(i + 1 + bypass_count)) {
-                cursor_positions.push((
-                    x_offset + metrics.advance_width / window.borrow().inner_size().width as f32,
-                    -line_shift, //  metrics.ymin as f32 / window.borrow().inner_size().height as f32
-                ));
-            }
-
-            if let Some(allocation) = allocator.allocate(size) {
+    // every distinct glyph is rasterized and uploaded to the atlas once
+    let mut glyph_cache: HashMap<(char, u32), Option<[f32; 4]>> = HashMap::new();
+    let mut atlas = |ch: char, font_size: f32| {
+        *glyph_cache
+            .entry((ch, font_size.to_bits()))
+            .or_insert_with(|| {
+                let (metrics, bitmap) = font.rasterize(ch, font_size);
+                // tracing::warn!("\n\n {:?}, Metrics {:?}\n\n", ch, metrics);
+                let size = Size::new(
+                    metrics.width as i32 + padding * 2,
+                    metrics.height as i32 + padding * 2,
+                );
+                let Some(allocation) = allocator.allocate(size) else {
+                    tracing::warn!("allocation failed");
+                    return None;
+                };
                 // tracing::warn!("\n\n allocation.rectangle {:?}\n\n", allocation.rectangle);
 
                 let encoder = device.create_command_encoder(&CommandEncoderDescriptor {
@@ -202,125 +184,28 @@ pub fn update_renderer(
                 let top_left_y = (allocation.rectangle.min.y + padding) as f32 / 2048.;
                 let bottom_right_x = (allocation.rectangle.max.x - padding) as f32 / 2048.;
                 let bottom_right_y = (allocation.rectangle.max.y - padding) as f32 / 2048.;
-                vertices.extend_from_slice(&[
-                    Vertex {
-                        position: [
-                            -1.0 + x_offset,
-                            char_height + y_offset + 1.0 - font_size_scale,
-                            0.0,
-                        ],
-                        tex_coords: [top_left_x, top_left_y],
-                    },
-                    Vertex {
-                        position: [-1.0 + x_offset, y_offset + 1.0 - font_size_scale, 0.0],
-                        tex_coords: [top_left_x, bottom_right_y],
-                    },
-                    Vertex {
-                        position: [
-                            char_width + x_offset - 1.0,
-                            y_offset + 1.0 - font_size_scale,
-                            0.0,
-                        ],
-                        tex_coords: [bottom_right_x, bottom_right_y],
-                    },
-                    Vertex {
-                        position: [
-                            char_width + x_offset - 1.0,
-                            char_height + y_offset + 1.0 - font_size_scale,
-                            0.0,
-                        ],
-                        tex_coords: [bottom_right_x, top_left_y],
-                    },
-                ]);
-                indices.extend_from_slice(&[
-                    0 + i as u16 * 4,
-                    1 + i as u16 * 4,
-                    2 + i as u16 * 4,
-                    2 + i as u16 * 4,
-                    3 + i as u16 * 4,
-                    0 + i as u16 * 4,
-                ]);
-                x_offset += metrics.advance_width / window.borrow().inner_size().width as f32
-            } else {
-                tracing::warn!("allocation failed");
+                Some([top_left_x, top_left_y, bottom_right_x, bottom_right_y])
+            })
+    };
+
+    // every scene is laid out in a clip space of its own, then moved into
+    // its part of the window and clipped to it
+    let (window_width, window_height) = (config.width as f32, config.height as f32);
+    let mut draws = vec![];
+    for (scene, rect) in scenes {
+        let quads = match scene {
+            Scene::Text(view, layout) => text_quads(view, layout, font, &mut atlas),
+            Scene::Tabs(labels, active, layout) => {
+                tab_quads(labels, *active, layout, font, &mut atlas)
             }
+            Scene::Frame(focused) => frame_quads(rect, *focused),
+        };
+        let start = indices.len() as u32;
+        for q in quads.iter() {
+            let q = place(q, rect, window_width, window_height);
+            push_quad(&mut vertices, &mut indices, &q);
         }
-    }
-    if indices.len() == 0 {
-        cursor_positions.push((0.0, 0.0));
-    }
-    let char_len = indices.len() / 6;
-    // after all characters have been processed, update the cursor positions
-    for (i, (x, y)) in cursor_positions.iter().enumerate() {
-        // log::warn!("cursor position: {}, {}", x, y);
-        // Get metrics for a capital letter to approximate ascent
-        let capital_metrics = font.rasterize('H', font_size).0;
-        // let ascent_approx =
-        //     capital_metrics.height as f32 / window.borrow().inner_size().height as f32;
-
-        // Get metrics for a character with a descender to approximate descent
-        let descender_metrics = font.rasterize('g', font_size).0;
-        // let descent_approx = descender_metrics.height as f32
-        //     / window.borrow().inner_size().height as f32
-        //     - ascent_approx;
-        // let baseline = ascent_approx - descent_approx;
-        // log::warn!(
-        //     "ascent_approx: {}, descent_approx: {}, baseline: {}",
-        //     ascent_approx,
-        //     descent_approx,
-        //     baseline
-        // );
-        let x = *x - 1.0;
-        let y = 1.0 + y;
-        let y = y
-            - (capital_metrics.height as f32
-                + descender_metrics.height as f32
-                + capital_metrics.ymin as f32
-                + descender_metrics.ymin as f32)
-                / window.borrow().inner_size().height as f32;
-        let cursor_width = capital_metrics.width as f32 / window.borrow().inner_size().width as f32;
-        let cursor_height = 16.0 / window.borrow().inner_size().height as f32;
-
-        let cursor_top_left_x = x;
-        let cursor_top_left_y = y;
-        let cursor_bottom_right_x = x + cursor_width;
-        let cursor_bottom_right_y = y - cursor_height;
-
-        // log::warn!(
-        //     "cursor_top_left_x: {}, cursor_top_left_y: {}, cursor_bottom_right_x: {}, cursor_bottom_right_y: {}",
-        //     cursor_top_left_x,
-        //     cursor_top_left_y,
-        //     cursor_bottom_right_x,
-        //     cursor_bottom_right_y
-        // );
-
-        let i = i + char_len;
-        vertices.extend_from_slice(&[
-            Vertex {
-                position: [cursor_top_left_x, cursor_top_left_y, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-            Vertex {
-                position: [cursor_top_left_x, cursor_bottom_right_y, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-            Vertex {
-                position: [cursor_bottom_right_x, cursor_bottom_right_y, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-            Vertex {
-                position: [cursor_bottom_right_x, cursor_top_left_y, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-        ]);
-        indices.extend_from_slice(&[
-            0 + i as u16 * 4,
-            1 + i as u16 * 4,
-            2 + i as u16 * 4,
-            2 + i as u16 * 4,
-            3 + i as u16 * 4,
-            0 + i as u16 * 4,
-        ]);
+        draws.push((start..indices.len() as u32, *rect));
     }
 
     let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -393,10 +278,7 @@ pub fn update_renderer(
             entry_point: "fs_main",
             targets: &[Some(wgpu::ColorTargetState {
                 format: config.format,
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent::REPLACE,
-                    alpha: wgpu::BlendComponent::REPLACE,
-                }),
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
         }),
@@ -434,13 +316,370 @@ pub fn update_renderer(
         contents: bytemuck::cast_slice(&indices),
         usage: wgpu::BufferUsages::INDEX,
     });
-    let num_indices = indices.len() as u32;
 
     (
         render_pipeline,
         vertex_buffer,
         index_buffer,
-        num_indices,
+        draws,
         diffuse_bind_group,
     )
 }
+
+// the quads of a text view in the clip space of its layout: backgrounds first
+// so that the glyphs are drawn on top of them, carets last
+fn text_quads(
+    view: &TextView,
+    layout: &TextLayout,
+    font: &Font,
+    atlas: &mut impl FnMut(char, f32) -> Option<[f32; 4]>,
+) -> Vec<[Vertex; 4]> {
+    let width = layout.width;
+    let height = layout.height;
+    let buffer = view.buffer;
+    let cursors = view.cursors;
+
+    let mut background_quads = vec![];
+    let mut glyph_quads = vec![];
+
+    let font_size = layout.font_size;
+    let font_size_scale = layout.font_size_scale;
+    let line_height = layout.line_height;
+    // Get metrics for a capital letter to approximate ascent
+    let capital_metrics = font.metrics('H', font_size);
+    // Get metrics for a character with a descender to approximate descent
+    let descender_metrics = font.metrics('g', font_size);
+
+    let mut glyph_quad = |ch: char, x_offset: f32, line_shift: f32, color: [f32; 4]| {
+        let tex = atlas(ch, font_size)?;
+        Some(glyph_at(layout, font, tex, ch, x_offset, line_shift, color))
+    };
+    let band = |left: f32, right: f32, line_shift: f32, color: [f32; 4]| {
+        line_band(layout, left, right, line_shift, color)
+    };
+
+    // only the rows inside the viewport are laid out
+    let rows = layout.visible_rows(buffer, font);
+    let end_char = rows.last().unwrap().range.end;
+
+    // the gutter: markers in the first column, then right-aligned line numbers
+    // on the first row of every line
+    let digit_advance = font.metrics('0', font_size).advance_width / width;
+    let cursor_lines: Vec<usize> = cursors
+        .iter()
+        .map(|c| buffer.char_to_line(c.head))
+        .collect();
+    for (row_index, row) in rows.iter().enumerate() {
+        let line = row.line;
+        let line_shift = row_index as f32 * line_height;
+        if view.error_line == Some(line) {
+            background_quads.push(band(0.0, layout.gutter_width, line_shift, ERROR_COLOR));
+        } else if view.changed_lines.contains(&line) {
+            background_quads.push(band(0.0, digit_advance / 3.0, line_shift, CHANGED_COLOR));
+        }
+        if row.range.start != buffer.line_to_char(line) {
+            continue;
+        }
+        let color = if cursor_lines.contains(&line) {
+            TEXT_COLOR
+        } else {
+            GUTTER_COLOR
+        };
+        let number = (line + 1).to_string();
+        let mut x_offset = layout.gutter_width - (number.len() + 1) as f32 * digit_advance;
+        for ch in number.chars() {
+            glyph_quads.extend(glyph_quad(ch, x_offset, line_shift, color));
+            x_offset += digit_advance;
+        }
+        // chains that can fold get a toggle right of the numbers
+        if row.folded > 0 {
+            glyph_quads.extend(glyph_quad('›', layout.gutter_width, line_shift, TEXT_COLOR));
+        } else if crate::Folds::is_foldable(buffer, line) {
+            glyph_quads.extend(glyph_quad(
+                '⌄',
+                layout.gutter_width,
+                line_shift,
+                GUTTER_COLOR,
+            ));
+        }
+    }
+
+    let mut line_shift = 0.0;
+    let mut x_offset = layout.origin_x;
+    let mut cursor_positions = vec![];
+    // the first match that does not end before the current char
+    let mut next_match = view
+        .matches
+        .partition_point(|m| m.end <= rows[0].range.start);
+    for (row_index, row) in rows.iter().enumerate() {
+        line_shift = row_index as f32 * line_height;
+        x_offset = row.x;
+        for (i, ch) in row.range.clone().zip(buffer.chars_at(row.range.start)) {
+            let advance = font.metrics(ch, font_size).advance_width / width;
+
+            if cursors.iter().any(|c| c.head == i) {
+                if view.block_caret {
+                    // line breaks have no advance, the block is one digit wide
+                    let right = x_offset + if ch == '\n' { digit_advance } else { advance };
+                    background_quads.push(band(x_offset, right, line_shift, CURSOR_COLOR));
+                } else {
+                    cursor_positions.push((x_offset, -line_shift));
+                }
+            }
+
+            if cursors.iter().any(|c| c.contains(i)) {
+                background_quads.push(band(
+                    x_offset,
+                    x_offset + advance,
+                    line_shift,
+                    SELECTION_COLOR,
+                ));
+            }
+            while next_match < view.matches.len() && view.matches[next_match].end <= i {
+                next_match += 1;
+            }
+            if view.matches.get(next_match).is_some_and(|m| m.contains(&i)) {
+                background_quads.push(band(x_offset, x_offset + advance, line_shift, MATCH_COLOR));
+            }
+            if view.brackets.contains(&i) {
+                // a one pixel frame around the line band of the bracket
+                let (px, py) = (2.0 / width, 2.0 / height);
+                let center = 1.0 - font_size_scale - line_shift + layout.band_center;
+                let (top_y, bottom_y) = (center + line_height / 2.0, center - line_height / 2.0);
+                let (left_x, right_x) = (-1.0 + x_offset, -1.0 + x_offset + advance);
+                let edge = |l: f32, t: f32, r: f32, b: f32| {
+                    quad([l, t], [r, b], [0.0, 0.0, 0.0, 0.0], BRACKET_COLOR)
+                };
+                background_quads.push(edge(left_x, top_y, right_x, top_y - py));
+                background_quads.push(edge(left_x, bottom_y + py, right_x, bottom_y));
+                background_quads.push(edge(left_x, top_y, left_x + px, bottom_y));
+                background_quads.push(edge(right_x - px, top_y, right_x, bottom_y));
+            }
+
+            if ch == '\n' {
+                continue;
+            } else if ch != ' ' {
+                // spaces have no bitmap, only an advance
+                glyph_quads.extend(glyph_quad(ch, x_offset, line_shift, TEXT_COLOR));
+            }
+            x_offset += advance;
+        }
+        if row.folded > 0 {
+            // the placeholder for the folded lines
+            let text = format!("… {} lines", row.folded);
+            let mut x = x_offset + digit_advance;
+            let start = x;
+            for ch in text.chars() {
+                if ch != ' ' {
+                    glyph_quads.extend(glyph_quad(ch, x, line_shift, GUTTER_COLOR));
+                }
+                x += font.metrics(ch, font_size).advance_width / width;
+            }
+            let padding = digit_advance / 2.0;
+            background_quads.push(band(start - padding, x + padding, line_shift, PANEL_COLOR));
+        }
+    }
+    // the cursor can also sit after the last character
+    if end_char == buffer.len_chars() && cursors.iter().any(|c| c.head == end_char) {
+        if view.block_caret {
+            let right = x_offset + digit_advance;
+            background_quads.push(band(x_offset, right, line_shift, CURSOR_COLOR));
+        } else {
+            cursor_positions.push((x_offset, -line_shift));
+        }
+    }
+
+    // the panel covers the bottom rows, on top of the text
+    for (row, text) in view.status.iter().enumerate() {
+        let line_shift = layout.status_shift(row);
+        background_quads.push(band(0.0, 2.0, line_shift, PANEL_COLOR));
+        let mut x_offset = digit_advance;
+        for (col, ch) in text.chars().enumerate() {
+            if view.status_cursor == Some((row, col)) {
+                cursor_positions.push((x_offset, -line_shift));
+            }
+            if ch != ' ' {
+                glyph_quads.extend(glyph_quad(ch, x_offset, line_shift, TEXT_COLOR));
+            }
+            x_offset += font.metrics(ch, font_size).advance_width / width;
+        }
+        if view.status_cursor == Some((row, text.chars().count())) {
+            cursor_positions.push((x_offset, -line_shift));
+        }
+    }
+
+    // after all characters have been processed, update the cursor positions
+    let mut cursor_quads = vec![];
+    for (x, y) in cursor_positions.iter() {
+        // log::warn!("cursor position: {}, {}", x, y);
+        let x = *x - 1.0;
+        let y = 1.0 + y;
+        let y = y
+            - (capital_metrics.height as f32
+                + descender_metrics.height as f32
+                + capital_metrics.ymin as f32
+                + descender_metrics.ymin as f32)
+                / height;
+        let cursor_width = capital_metrics.width as f32 / width;
+        let cursor_height = 16.0 / height;
+
+        cursor_quads.push(quad(
+            [x, y],
+            [x + cursor_width, y - cursor_height],
+            [0.0, 0.0, 0.0, 0.0],
+            CURSOR_COLOR,
+        ));
+    }
+
+    background_quads
+        .into_iter()
+        .chain(glyph_quads)
+        .chain(cursor_quads)
+        .collect()
+}
+
+// a bar of tab labels on the first row of `layout`, the active one lighter
+fn tab_quads(
+    labels: &[String],
+    active: usize,
+    layout: &TextLayout,
+    font: &Font,
+    atlas: &mut impl FnMut(char, f32) -> Option<[f32; 4]>,
+) -> Vec<[Vertex; 4]> {
+    let font_size = layout.font_size;
+    let mut background_quads = vec![line_band(layout, 0.0, 2.0, 0.0, PANEL_COLOR)];
+    let mut glyph_quads = vec![];
+    let padding = font.metrics('0', font_size).advance_width / layout.width;
+    for (i, (label, span)) in labels
+        .iter()
+        .zip(layout.tab_spans(font, labels))
+        .enumerate()
+    {
+        let color = if i == active {
+            background_quads.push(line_band(
+                layout,
+                span.start,
+                span.end,
+                0.0,
+                ACTIVE_TAB_COLOR,
+            ));
+            TEXT_COLOR
+        } else {
+            GUTTER_COLOR
+        };
+        let mut x = span.start + padding;
+        for ch in label.chars() {
+            if ch != ' ' {
+                if let Some(tex) = atlas(ch, font_size) {
+                    glyph_quads.push(glyph_at(layout, font, tex, ch, x, 0.0, color));
+                }
+            }
+            x += font.metrics(ch, font_size).advance_width / layout.width;
+        }
+    }
+    background_quads.into_iter().chain(glyph_quads).collect()
+}
+
+// glyph `ch` with its left edge at `x_offset`, on the row `line_shift` down
+fn glyph_at(
+    layout: &TextLayout,
+    font: &Font,
+    tex: [f32; 4],
+    ch: char,
+    x_offset: f32,
+    line_shift: f32,
+    color: [f32; 4],
+) -> [Vertex; 4] {
+    let metrics = font.metrics(ch, layout.font_size);
+    let char_width = metrics.width as f32 / layout.width;
+    let char_height = (metrics.height as f32) / layout.height;
+    let y_offset = metrics.ymin as f32 / layout.height - line_shift;
+    quad(
+        [
+            -1.0 + x_offset,
+            char_height + y_offset + 1.0 - layout.font_size_scale,
+        ],
+        [
+            char_width + x_offset - 1.0,
+            y_offset + 1.0 - layout.font_size_scale,
+        ],
+        tex,
+        color,
+    )
+}
+
+// a rectangle as tall as a line, centred on the glyphs of row `line_shift`
+fn line_band(
+    layout: &TextLayout,
+    left: f32,
+    right: f32,
+    line_shift: f32,
+    color: [f32; 4],
+) -> [Vertex; 4] {
+    let center = 1.0 - layout.font_size_scale - line_shift + layout.band_center;
+    quad(
+        [-1.0 + left, center + layout.line_height / 2.0],
+        [-1.0 + right, center - layout.line_height / 2.0],
+        [0.0, 0.0, 0.0, 0.0],
+        color,
+    )
+}
+
+// the edges of `rect`, a pixel wide or two when focused
+fn frame_quads(rect: &Rect, focused: bool) -> Vec<[Vertex; 4]> {
+    let (thickness, color) = if focused {
+        (2.0, FOCUS_COLOR)
+    } else {
+        (1.0, GUTTER_COLOR)
+    };
+    let x = 2.0 * thickness / rect.width;
+    let y = 2.0 * thickness / rect.height;
+    let no_tex = [0.0, 0.0, 0.0, 0.0];
+    vec![
+        quad([-1.0, 1.0], [1.0, 1.0 - y], no_tex, color),
+        quad([-1.0, -1.0 + y], [1.0, -1.0], no_tex, color),
+        quad([-1.0, 1.0], [-1.0 + x, -1.0], no_tex, color),
+        quad([1.0 - x, 1.0], [1.0, -1.0], no_tex, color),
+    ]
+}
+
+/// The part of `rect` inside a `width` x `height` window, in whole pixels
+/// as `set_scissor_rect` takes them; `None` when nothing of it is left.
+pub fn scissor(rect: &Rect, width: u32, height: u32) -> Option<[u32; 4]> {
+    let left = rect.x.round().clamp(0.0, width as f32) as u32;
+    let top = rect.y.round().clamp(0.0, height as f32) as u32;
+    let right = (rect.x + rect.width).round().clamp(0.0, width as f32) as u32;
+    let bottom = (rect.y + rect.height).round().clamp(0.0, height as f32) as u32;
+    (right > left && bottom > top).then_some([left, top, right - left, bottom - top])
+}
+
+// moves a quad from the clip space of `rect` into that of the window
+fn place(q: &[Vertex; 4], rect: &Rect, window_width: f32, window_height: f32) -> [Vertex; 4] {
+    let mut q = *q;
+    for vertex in q.iter_mut() {
+        let [x, y, _] = vertex.position;
+        vertex.position[0] = (rect.x + (x + 1.0) / 2.0 * rect.width) / window_width * 2.0 - 1.0;
+        vertex.position[1] = 1.0 - (rect.y + (1.0 - y) / 2.0 * rect.height) / window_height * 2.0;
+    }
+    q
+}
+
+/// Milliseconds on a monotonic clock, e.g. for double-click detection.
+pub fn now_ms() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::window()
+            .and_then(|w| w.performance())
+            .map(|p| p.now())
+            .unwrap_or(0.0)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        START
+            .get_or_init(std::time::Instant::now)
+            .elapsed()
+            .as_secs_f64()
+            * 1000.0
+    }
+}
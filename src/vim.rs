@@ -0,0 +1,507 @@
+use std::ops::Range;
+
+use crate::{is_word_char, TextBuffer};
+
+/// The editing mode when Vim keys are enabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VimMode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl VimMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            VimMode::Normal => "-- NORMAL --",
+            VimMode::Insert => "-- INSERT --",
+            VimMode::Visual => "-- VISUAL --",
+            VimMode::VisualLine => "-- VISUAL LINE --",
+        }
+    }
+
+    pub fn is_visual(self) -> bool {
+        matches!(self, VimMode::Visual | VimMode::VisualLine)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// `w`
+    WordStart,
+    /// `b`
+    WordBack,
+    /// `e`
+    WordEnd,
+    /// `0`
+    LineStart,
+    /// `^`
+    FirstNonBlank,
+    /// `$`
+    LineEnd,
+    /// `gg`, or line `count` with a count
+    FirstLine,
+    /// `G`, or line `count` with a count
+    LastLine,
+}
+
+impl Motion {
+    fn from_char(ch: char) -> Option<Self> {
+        Some(match ch {
+            'h' => Motion::Left,
+            'l' | ' ' => Motion::Right,
+            'k' => Motion::Up,
+            'j' => Motion::Down,
+            'w' => Motion::WordStart,
+            'b' => Motion::WordBack,
+            'e' => Motion::WordEnd,
+            '0' => Motion::LineStart,
+            '^' => Motion::FirstNonBlank,
+            '$' => Motion::LineEnd,
+            'G' => Motion::LastLine,
+            _ => return None,
+        })
+    }
+
+    /// Operators on these motions act on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+
+    /// Operators on these motions include the char the motion lands on.
+    pub fn is_inclusive(self) -> bool {
+        self == Motion::WordEnd
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// What an operator acts on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Motion(Motion),
+    /// `dd`, `cc`, `yy`
+    Lines,
+    /// `i(` and friends; the char is the opening bracket, a quote or `w`
+    Inner(char),
+    /// `a(` and friends
+    Around(char),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertAt {
+    /// `i`
+    Before,
+    /// `a`
+    After,
+    /// `I`
+    LineStart,
+    /// `A`
+    LineEnd,
+    /// `o`
+    LineBelow,
+    /// `O`
+    LineAbove,
+}
+
+/// A complete normal or visual mode command. Counts are `None` when none
+/// was typed, which matters for `G`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VimCommand {
+    Move(Motion, Option<usize>),
+    Operate(Operator, Target, Option<usize>),
+    /// `x`
+    DeleteChar(usize),
+    /// `p`, `P`
+    Put {
+        before: bool,
+        count: usize,
+    },
+    Undo(usize),
+    Insert(InsertAt),
+    /// `v`, `V`
+    Visual(VimMode),
+    /// `d`, `x`, `c`, `y` on the visual selection
+    VisualOperate(Operator),
+}
+
+enum Parse {
+    Complete(VimCommand),
+    Incomplete,
+    Invalid,
+}
+
+/// Modal state: the current mode and the keys typed so far of a command.
+#[derive(Clone, Debug, Default)]
+pub struct Vim {
+    pub mode: VimMode,
+    pub pending: String,
+    /// where a visual selection started
+    pub visual_anchor: usize,
+}
+
+impl Vim {
+    /// Adds a typed char to the pending command and returns the command
+    /// once it is complete. Keys that make no sense are dropped.
+    pub fn feed(&mut self, ch: char) -> Option<VimCommand> {
+        self.pending.push(ch);
+        match parse(&self.pending, self.mode.is_visual()) {
+            Parse::Complete(command) => {
+                self.pending.clear();
+                Some(command)
+            }
+            Parse::Incomplete => None,
+            Parse::Invalid => {
+                self.pending.clear();
+                None
+            }
+        }
+    }
+}
+
+// the largest count a command takes; more would only hang or run out of
+// memory, e.g. putting the register a billion times
+const MAX_COUNT: usize = 10_000;
+
+// a count never starts with 0, which is the line start motion
+fn take_count(keys: &str) -> (Option<usize>, &str) {
+    let digits = keys
+        .char_indices()
+        .take_while(|(i, ch)| ch.is_ascii_digit() && !(*i == 0 && *ch == '0'))
+        .count();
+    if digits == 0 {
+        (None, keys)
+    } else {
+        let count = keys[..digits]
+            .parse()
+            .map_or(MAX_COUNT, |n: usize| n.min(MAX_COUNT));
+        (Some(count), &keys[digits..])
+    }
+}
+
+fn times(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (None, None) => None,
+        _ => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1)).min(MAX_COUNT)),
+    }
+}
+
+fn parse(keys: &str, visual: bool) -> Parse {
+    let (count, rest) = take_count(keys);
+    let n = count.unwrap_or(1);
+    let mut chars = rest.chars();
+    let Some(first) = chars.next() else {
+        return Parse::Incomplete;
+    };
+    let after = chars.as_str();
+
+    if let Some(motion) = Motion::from_char(first) {
+        return Parse::Complete(VimCommand::Move(motion, count));
+    }
+    let operator = match first {
+        'd' | 'x' if visual => return Parse::Complete(VimCommand::VisualOperate(Operator::Delete)),
+        'c' if visual => return Parse::Complete(VimCommand::VisualOperate(Operator::Change)),
+        'y' if visual => return Parse::Complete(VimCommand::VisualOperate(Operator::Yank)),
+        'd' => Operator::Delete,
+        'c' => Operator::Change,
+        'y' => Operator::Yank,
+        'g' => {
+            return match after {
+                "" => Parse::Incomplete,
+                "g" => Parse::Complete(VimCommand::Move(Motion::FirstLine, count)),
+                _ => Parse::Invalid,
+            }
+        }
+        'x' => return Parse::Complete(VimCommand::DeleteChar(n)),
+        'D' => {
+            let target = Target::Motion(Motion::LineEnd);
+            return Parse::Complete(VimCommand::Operate(Operator::Delete, target, None));
+        }
+        'C' => {
+            let target = Target::Motion(Motion::LineEnd);
+            return Parse::Complete(VimCommand::Operate(Operator::Change, target, None));
+        }
+        'p' => {
+            return Parse::Complete(VimCommand::Put {
+                before: false,
+                count: n,
+            })
+        }
+        'P' => {
+            return Parse::Complete(VimCommand::Put {
+                before: true,
+                count: n,
+            })
+        }
+        'u' => return Parse::Complete(VimCommand::Undo(n)),
+        'v' => return Parse::Complete(VimCommand::Visual(VimMode::Visual)),
+        'V' => return Parse::Complete(VimCommand::Visual(VimMode::VisualLine)),
+        _ if visual => return Parse::Invalid,
+        'i' => return Parse::Complete(VimCommand::Insert(InsertAt::Before)),
+        'a' => return Parse::Complete(VimCommand::Insert(InsertAt::After)),
+        'I' => return Parse::Complete(VimCommand::Insert(InsertAt::LineStart)),
+        'A' => return Parse::Complete(VimCommand::Insert(InsertAt::LineEnd)),
+        'o' => return Parse::Complete(VimCommand::Insert(InsertAt::LineBelow)),
+        'O' => return Parse::Complete(VimCommand::Insert(InsertAt::LineAbove)),
+        _ => return Parse::Invalid,
+    };
+
+    // an operator, then an optional count and what it acts on
+    let (count2, target) = take_count(after);
+    let count = times(count, count2);
+    let mut chars = target.chars();
+    let Some(key) = chars.next() else {
+        return Parse::Incomplete;
+    };
+    let target = match (key, chars.as_str()) {
+        (_, "") if key == first => Target::Lines,
+        ('i' | 'a', "") | ('g', "") => return Parse::Incomplete,
+        ('g', "g") => Target::Motion(Motion::FirstLine),
+        ('i' | 'a', object) => {
+            let Some(object) = text_object_char(object) else {
+                return Parse::Invalid;
+            };
+            if key == 'i' {
+                Target::Inner(object)
+            } else {
+                Target::Around(object)
+            }
+        }
+        (key, "") => match Motion::from_char(key) {
+            Some(motion) => Target::Motion(motion),
+            None => return Parse::Invalid,
+        },
+        _ => return Parse::Invalid,
+    };
+    Parse::Complete(VimCommand::Operate(operator, target, count))
+}
+
+// `b` and `)` stand for `(`, `B` and `}` for `{`, `]` for `[`
+fn text_object_char(keys: &str) -> Option<char> {
+    let mut chars = keys.chars();
+    let ch = chars.next()?;
+    if !chars.as_str().is_empty() {
+        return None;
+    }
+    Some(match ch {
+        '(' | ')' | 'b' => '(',
+        '[' | ']' => '[',
+        '{' | '}' | 'B' => '{',
+        '"' | '\'' | 'w' => ch,
+        _ => return None,
+    })
+}
+
+// 0 for whitespace, 1 for words (a `~` starting a name included), 2 for
+// other punctuation
+fn class(buffer: &TextBuffer, pos: usize) -> u8 {
+    let ch = buffer.char(pos);
+    if ch.is_whitespace() {
+        0
+    } else if is_word_char(ch) || (ch == '~' && buffer.get_char(pos + 1).is_some_and(is_word_char))
+    {
+        1
+    } else {
+        2
+    }
+}
+
+// `w`: past the rest of the current word or punctuation, then past spaces
+fn next_word_start(buffer: &TextBuffer, pos: usize) -> usize {
+    let len = buffer.len_chars();
+    if pos >= len {
+        return len;
+    }
+    let start_class = class(buffer, pos);
+    let mut pos = pos;
+    while pos < len && start_class != 0 && class(buffer, pos) == start_class {
+        pos += 1;
+    }
+    while pos < len && class(buffer, pos) == 0 {
+        pos += 1;
+    }
+    pos
+}
+
+// `e`: the last char of the current or next word
+fn word_end(buffer: &TextBuffer, pos: usize) -> usize {
+    let len = buffer.len_chars();
+    let mut pos = pos + 1;
+    while pos < len && class(buffer, pos) == 0 {
+        pos += 1;
+    }
+    if pos >= len {
+        return len.saturating_sub(1);
+    }
+    let word_class = class(buffer, pos);
+    while pos + 1 < len && class(buffer, pos + 1) == word_class {
+        pos += 1;
+    }
+    pos
+}
+
+/// Where `motion` repeated `count` times takes a cursor at `pos`.
+pub fn motion_target(
+    buffer: &TextBuffer,
+    pos: usize,
+    motion: Motion,
+    count: Option<usize>,
+) -> usize {
+    let n = count.unwrap_or(1);
+    let (line, col) = buffer.char_to_line_col(pos);
+    let last_line = buffer.len_lines() - 1;
+    match motion {
+        Motion::Left => pos - col.min(n),
+        Motion::Right => pos.saturating_add(n).min(buffer.line_end(line)),
+        Motion::Up => buffer.line_col_to_char(line.saturating_sub(n), col),
+        Motion::Down => buffer.line_col_to_char(line.saturating_add(n).min(last_line), col),
+        Motion::WordStart => (0..n).fold(pos, |p, _| next_word_start(buffer, p)),
+        Motion::WordBack => (0..n).fold(pos, |p, _| buffer.prev_word_boundary(p)),
+        Motion::WordEnd => (0..n).fold(pos, |p, _| word_end(buffer, p)),
+        Motion::LineStart => buffer.line_to_char(line),
+        Motion::FirstNonBlank => buffer.line_indent_end(line),
+        Motion::LineEnd => buffer.line_end(line),
+        Motion::FirstLine => {
+            let line = count.map_or(0, |n| n - 1).min(last_line);
+            buffer.line_indent_end(line)
+        }
+        Motion::LastLine => {
+            let line = count.map_or(last_line, |n| n - 1).min(last_line);
+            buffer.line_indent_end(line)
+        }
+    }
+}
+
+/// The range of the text object `object` around `pos`: a bracket pair, a
+/// quoted string on the line, or a word. `around` includes the brackets or
+/// quotes, or the spaces after a word.
+pub fn text_object(
+    buffer: &TextBuffer,
+    pos: usize,
+    object: char,
+    around: bool,
+) -> Option<Range<usize>> {
+    match object {
+        'w' => {
+            let word = buffer.word_at(pos)?;
+            if !around {
+                return Some(word);
+            }
+            let mut end = word.end;
+            while buffer
+                .get_char(end)
+                .is_some_and(|ch| ch == ' ' || ch == '\t')
+            {
+                end += 1;
+            }
+            Some(word.start..end)
+        }
+        '"' | '\'' => {
+            let line = buffer.char_to_line(pos);
+            let start = buffer.line_to_char(line);
+            let quotes: Vec<usize> = (start..buffer.line_end(line))
+                .filter(|&i| buffer.char(i) == object)
+                .collect();
+            let pair = quotes
+                .chunks_exact(2)
+                .find(|pair| pair[0] <= pos && pos <= pair[1])?;
+            if around {
+                Some(pair[0]..pair[1] + 1)
+            } else {
+                Some(pair[0] + 1..pair[1])
+            }
+        }
+        open => {
+            let close = crate::closing_pair(open)?;
+            // walk back to the unmatched opening bracket around `pos`
+            let mut depth = 0;
+            let mut at = pos.min(buffer.len_chars());
+            let open_at = loop {
+                match buffer.get_char(at) {
+                    Some(ch) if ch == open && depth == 0 => break at,
+                    Some(ch) if ch == open => depth -= 1,
+                    Some(ch) if ch == close && at != pos => depth += 1,
+                    _ => {}
+                }
+                at = at.checked_sub(1)?;
+            };
+            let (_, close_at) = crate::matching_bracket(buffer, open_at)?;
+            if around {
+                Some(open_at..close_at + 1)
+            } else {
+                Some(open_at + 1..close_at)
+            }
+        }
+    }
+}
+
+/// The chars of lines `lines`, with the line break after the last one, or
+/// before the first one when the range reaches the end of the text.
+pub fn line_span(buffer: &TextBuffer, lines: Range<usize>) -> Range<usize> {
+    let start = buffer.line_to_char(lines.start);
+    if lines.end < buffer.len_lines() {
+        start..buffer.line_to_char(lines.end)
+    } else if lines.start > 0 {
+        buffer.line_end(lines.start - 1)..buffer.len_chars()
+    } else {
+        start..buffer.len_chars()
+    }
+}
+
+/// The range `target` covers from a cursor at `pos`, and whether it is
+/// made of whole lines; line ranges are line numbers, see `line_span`.
+pub fn target_range(
+    buffer: &TextBuffer,
+    pos: usize,
+    operator: Operator,
+    target: Target,
+    count: Option<usize>,
+) -> Option<(Range<usize>, bool)> {
+    let line = buffer.char_to_line(pos);
+    match target {
+        Target::Lines => {
+            let end = line
+                .saturating_add(count.unwrap_or(1))
+                .min(buffer.len_lines());
+            Some((line..end, true))
+        }
+        Target::Inner(object) => text_object(buffer, pos, object, false).map(|r| (r, false)),
+        Target::Around(object) => text_object(buffer, pos, object, true).map(|r| (r, false)),
+        Target::Motion(motion) => {
+            // `cw` on a word changes only to its end, like `ce`
+            let motion = if operator == Operator::Change
+                && motion == Motion::WordStart
+                && pos < buffer.len_chars()
+                && class(buffer, pos) != 0
+            {
+                Motion::WordEnd
+            } else {
+                motion
+            };
+            let to = motion_target(buffer, pos, motion, count);
+            if motion.is_linewise() {
+                let other = buffer.char_to_line(to);
+                return Some((line.min(other)..line.max(other) + 1, true));
+            }
+            let mut range = pos.min(to)..pos.max(to);
+            if motion.is_inclusive() {
+                range.end = (range.end + 1).min(buffer.len_chars());
+            }
+            Some((range, false))
+        }
+    }
+}
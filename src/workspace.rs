@@ -0,0 +1,594 @@
+use std::{cell::RefCell, path::Path, rc::Rc};
+
+use winit::{dpi::PhysicalSize, event::*};
+
+use crate::{Clipboard, Editor, Effect, Panes, Rect, Scene, TextLayout};
+
+/// The open buffers, each with its own cursors, undo history and scroll, a
+/// bar of tabs, and the panes that show them under it.
+pub struct Workspace {
+    tabs: Vec<Tab>,
+    // the buffer of the focused pane
+    active: usize,
+    next_id: usize,
+    // a buffer is shown in one pane at most
+    panes: Panes,
+    // the window in physical pixels, and the last pointer position in it
+    size: PhysicalSize<u32>,
+    scale_factor: f64,
+    mouse_position: (f64, f64),
+    effects: Vec<(usize, Effect)>,
+    // one clipboard for all buffers, so copying in one pastes in another
+    // without a system clipboard too
+    clipboard: Rc<RefCell<Clipboard>>,
+}
+
+struct Tab {
+    // stays the same while the buffer is open, unlike its place in the bar
+    id: usize,
+    editor: Editor,
+}
+
+impl Workspace {
+    pub fn new(editor: Editor) -> Self {
+        let mut workspace = Self {
+            tabs: vec![],
+            active: 0,
+            next_id: 0,
+            panes: Panes::Pane(0),
+            size: PhysicalSize::new(800, 600),
+            scale_factor: 1.0,
+            mouse_position: (0.0, 0.0),
+            effects: vec![],
+            clipboard: Rc::default(),
+        };
+        workspace.open(editor);
+        workspace
+    }
+
+    /// Adds a buffer after the others and shows it in the focused pane;
+    /// returns its id.
+    pub fn open(&mut self, editor: Editor) -> usize {
+        let id = self.add(editor);
+        self.show(id);
+        id
+    }
+
+    // adds a buffer without showing it
+    fn add(&mut self, mut editor: Editor) -> usize {
+        editor.set_clipboard(self.clipboard.clone());
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tabs.push(Tab { id, editor });
+        id
+    }
+
+    // an empty untitled buffer, with the settings of the focused one
+    fn empty_editor(&self) -> Editor {
+        let editor = self.editor();
+        Editor::new("", editor.settings().clone(), editor.keymap().clone())
+    }
+
+    /// Shows buffer `id` in the focused pane, or focuses the pane that
+    /// shows it already.
+    pub fn show(&mut self, id: usize) -> bool {
+        let Some(i) = self.tabs.iter().position(|tab| tab.id == id) else {
+            return false;
+        };
+        if !self.panes.contains(id) {
+            let focused = self.active_id();
+            self.panes.replace(focused, id);
+        }
+        self.active = i;
+        self.layout_panes();
+        true
+    }
+
+    /// Shows the buffer editing `path`, if one is open.
+    pub fn show_path(&mut self, path: &Path) -> bool {
+        match self.tabs.iter().find(|tab| tab.editor.path() == Some(path)) {
+            Some(tab) => self.show(tab.id),
+            None => false,
+        }
+    }
+
+    /// The buffer of the focused pane.
+    pub fn editor(&self) -> &Editor {
+        &self.tabs[self.active].editor
+    }
+
+    pub fn editor_mut(&mut self) -> &mut Editor {
+        &mut self.tabs[self.active].editor
+    }
+
+    /// The id of the buffer of the focused pane.
+    pub fn active_id(&self) -> usize {
+        self.tabs[self.active].id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Editor> {
+        let tab = self.tabs.iter().find(|tab| tab.id == id)?;
+        Some(&tab.editor)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Editor> {
+        let tab = self.tabs.iter_mut().find(|tab| tab.id == id)?;
+        Some(&mut tab.editor)
+    }
+
+    /// Every buffer with its id, in tab order.
+    pub fn editors(&self) -> impl Iterator<Item = (usize, &Editor)> {
+        self.tabs.iter().map(|tab| (tab.id, &tab.editor))
+    }
+
+    pub fn editors_mut(&mut self) -> impl Iterator<Item = (usize, &mut Editor)> {
+        self.tabs.iter_mut().map(|tab| (tab.id, &mut tab.editor))
+    }
+
+    /// The ids of the buffers shown, left to right and top to bottom.
+    pub fn shown(&self) -> Vec<usize> {
+        self.panes.ids()
+    }
+
+    pub fn resize(&mut self, size: PhysicalSize<u32>, scale_factor: f64) {
+        self.size = size;
+        self.scale_factor = scale_factor;
+        self.layout_panes();
+    }
+
+    // the tab bar is one row tall
+    fn tab_bar_layout(&self) -> TextLayout {
+        let font = self.editor().font();
+        let window = TextLayout::new(self.size, self.scale_factor, font, 0, 1);
+        let height = (window.rows_height_px(1).ceil() as u32).clamp(1, self.size.height.max(1));
+        let size = PhysicalSize::new(self.size.width, height);
+        TextLayout::new(size, self.scale_factor, font, 0, 1)
+    }
+
+    fn tab_bar_height(&self) -> f32 {
+        self.tab_bar_layout().height
+    }
+
+    // every pane in the space under the tab bar
+    fn pane_rects(&self) -> Vec<(usize, Rect)> {
+        let bar = self.tab_bar_height();
+        self.panes.rects(Rect {
+            x: 0.0,
+            y: bar,
+            width: self.size.width as f32,
+            height: (self.size.height as f32 - bar).max(1.0),
+        })
+    }
+
+    fn pane_at(&self, x: f64, y: f64) -> Option<(usize, Rect)> {
+        let rects = self.pane_rects();
+        rects
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x as f32, y as f32))
+    }
+
+    // lays each shown buffer out for its own pane
+    fn layout_panes(&mut self) {
+        let scale_factor = self.scale_factor;
+        for (id, rect) in self.pane_rects() {
+            let size = PhysicalSize::new(rect.width.max(1.0) as u32, rect.height.max(1.0) as u32);
+            if let Some(editor) = self.get_mut(id) {
+                editor.resize(size, scale_factor);
+            }
+        }
+    }
+
+    fn labels(&self) -> Vec<String> {
+        self.tabs
+            .iter()
+            .map(|tab| match tab.editor.own_engine() {
+                true => format!("{} (own engine)", tab.editor.name()),
+                false => tab.editor.name(),
+            })
+            .collect()
+    }
+
+    /// What to draw, and where: the tab bar, then every pane, framed when
+    /// there are several so the focused one stands out.
+    pub fn scenes(&self) -> Vec<(Scene<'_>, Rect)> {
+        let bar = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.size.width as f32,
+            height: self.tab_bar_height(),
+        };
+        let mut scenes = vec![(
+            Scene::Tabs(self.labels(), self.active, self.tab_bar_layout()),
+            bar,
+        )];
+        let rects = self.pane_rects();
+        for &(id, rect) in &rects {
+            let Some(editor) = self.get(id) else {
+                continue;
+            };
+            scenes.push((Scene::Text(editor.view(), editor.layout()), rect));
+            if rects.len() > 1 {
+                scenes.push((Scene::Frame(id == self.active_id()), rect));
+            }
+        }
+        scenes
+    }
+
+    /// Handles a window event; true when it changed anything. Clicks on the
+    /// tab bar show that buffer and clicks on a pane focus it; everything
+    /// else goes to the focused pane, and the wheel to the one under the
+    /// pointer.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let bar = self.tab_bar_height() as f64;
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse_position = (position.x, position.y);
+                return self.move_pointer(self.active_id());
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let (x, y) = self.mouse_position;
+                if y < bar {
+                    let layout = self.tab_bar_layout();
+                    let x = (2.0 * x / self.size.width as f64) as f32;
+                    let spans = layout.tab_spans(self.editor().font(), &self.labels());
+                    return match spans.iter().position(|span| span.contains(&x)) {
+                        Some(i) if i != self.active => self.show(self.tabs[i].id),
+                        _ => false,
+                    };
+                }
+                if let Some((id, _)) = self.pane_at(x, y) {
+                    if id != self.active_id() {
+                        self.show(id);
+                        self.move_pointer(id);
+                    }
+                }
+            }
+            WindowEvent::MouseWheel { .. } => {
+                let (x, y) = self.mouse_position;
+                if let Some((id, _)) = self.pane_at(x, y) {
+                    let editor = self.get_mut(id).unwrap();
+                    return editor.input(event);
+                }
+            }
+            _ => {}
+        }
+        // modifiers are tracked by every editor, so none of them thinks a
+        // key is still held after switching away while it was down
+        if let WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    virtual_keycode: Some(keycode),
+                    ..
+                },
+            ..
+        } = event
+        {
+            if crate::is_modifier(*keycode) {
+                for (i, tab) in self.tabs.iter_mut().enumerate() {
+                    if i != self.active {
+                        tab.editor.detect_modifiers(event);
+                    }
+                }
+            }
+        }
+        let handled = self.editor_mut().input(event);
+        self.collect_effects();
+        handled
+    }
+
+    // tells the editor of pane `id` where the pointer is, from the top left
+    // corner of the pane
+    fn move_pointer(&mut self, id: usize) -> bool {
+        let Some((_, rect)) = self.pane_rects().into_iter().find(|(pane, _)| *pane == id) else {
+            return false;
+        };
+        let (x, y) = self.mouse_position;
+        #[allow(deprecated)]
+        let event = WindowEvent::CursorMoved {
+            // safe for events that never reach the platform
+            device_id: unsafe { DeviceId::dummy() },
+            position: (x - rect.x as f64, y - rect.y as f64).into(),
+            modifiers: ModifiersState::empty(),
+        };
+        self.get_mut(id).is_some_and(|editor| editor.input(&event))
+    }
+
+    /// Whether the window may close: each buffer with unsaved changes is
+    /// shown and asks for a second close in turn.
+    pub fn request_close(&mut self) -> bool {
+        for i in 0..self.tabs.len() {
+            if !self.tabs[i].editor.request_close() {
+                self.show(self.tabs[i].id);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Takes what the buffers asked for since the last call, with the id of
+    /// the buffer that asked. Switching, opening empty buffers, closing and
+    /// everything about panes happen right away; a closed buffer still
+    /// reports `CloseBuffer` so its file and engine can be let go of.
+    pub fn take_effects(&mut self) -> Vec<(usize, Effect)> {
+        self.collect_effects();
+        std::mem::take(&mut self.effects)
+    }
+
+    // moves the effects of the buffers here, acting on those that switch
+    // between them
+    fn collect_effects(&mut self) {
+        let mut effects = vec![];
+        for tab in &mut self.tabs {
+            effects.extend(tab.editor.take_effects().into_iter().map(|e| (tab.id, e)));
+        }
+        effects.retain(|(id, effect)| {
+            let n = self.tabs.len();
+            match effect {
+                Effect::NextBuffer => {
+                    self.show(self.tabs[(self.active + 1) % n].id);
+                }
+                Effect::PreviousBuffer => {
+                    self.show(self.tabs[(self.active + n - 1) % n].id);
+                }
+                Effect::NewBuffer => {
+                    let editor = self.empty_editor();
+                    self.open(editor);
+                }
+                Effect::SplitRight => self.split(false),
+                Effect::SplitDown => self.split(true),
+                Effect::ClosePane => self.close_pane(),
+                Effect::FocusLeft => self.move_focus(-1.0, 0.0),
+                Effect::FocusRight => self.move_focus(1.0, 0.0),
+                Effect::FocusUp => self.move_focus(0.0, -1.0),
+                Effect::FocusDown => self.move_focus(0.0, 1.0),
+                Effect::CloseBuffer => {
+                    self.close(*id);
+                    return true;
+                }
+                _ => return true,
+            };
+            false
+        });
+        self.effects.extend(effects);
+    }
+
+    // splits the focused pane; the new pane shows the first buffer after
+    // the focused one that no pane shows, or a new empty one, and takes the
+    // focus
+    fn split(&mut self, stacked: bool) {
+        let n = self.tabs.len();
+        let hidden = (1..n)
+            .map(|k| self.tabs[(self.active + k) % n].id)
+            .find(|id| !self.panes.contains(*id));
+        let id = match hidden {
+            Some(id) => id,
+            None => {
+                let editor = self.empty_editor();
+                self.add(editor)
+            }
+        };
+        self.panes.split(self.active_id(), id, stacked);
+        self.show(id);
+    }
+
+    // closes the focused pane, unless it is the last one; its buffer stays
+    // open and the pane before it takes the focus
+    fn close_pane(&mut self) {
+        let focused = self.active_id();
+        let i = self.panes.ids().iter().position(|id| *id == focused);
+        if let (Some(i), true) = (i, self.panes.remove(focused)) {
+            let ids = self.panes.ids();
+            self.show(ids[i.saturating_sub(1).min(ids.len() - 1)]);
+        }
+    }
+
+    // focuses the nearest pane on the side of the focused one that `dx`
+    // and `dy` point to; of panes as near, the one level with its top or
+    // left edge
+    fn move_focus(&mut self, dx: f32, dy: f32) {
+        let rects = self.pane_rects();
+        let focused = self.active_id();
+        let Some(&(_, from)) = rects.iter().find(|(id, _)| *id == focused) else {
+            return;
+        };
+        let center = |r: &Rect| (r.x + r.width / 2.0, r.y + r.height / 2.0);
+        let (cx, cy) = center(&from);
+        let next = rects
+            .iter()
+            .filter(|(_, r)| {
+                // panes that face the focused one across that side
+                let overlaps = if dx != 0.0 {
+                    r.y < from.y + from.height && from.y < r.y + r.height
+                } else {
+                    r.x < from.x + from.width && from.x < r.x + r.width
+                };
+                let (x, y) = center(r);
+                overlaps && (x - cx) * dx + (y - cy) * dy > 0.0
+            })
+            .min_by(|(_, a), (_, b)| {
+                let distance = |r: &Rect| {
+                    let (x, y) = center(r);
+                    (
+                        (x - cx) * dx + (y - cy) * dy,
+                        ((r.x - from.x) * dy + (r.y - from.y) * dx).abs(),
+                    )
+                };
+                distance(a).partial_cmp(&distance(b)).unwrap()
+            });
+        if let Some(&(id, _)) = next {
+            self.show(id);
+        }
+    }
+
+    // the buffer is let go of along with its pane, or when it is the only
+    // pane, the buffer on its right takes its place; closing the last
+    // buffer leaves an empty one
+    fn close(&mut self, id: usize) {
+        if self.get(id).is_none() {
+            return;
+        }
+        if self.tabs.len() == 1 {
+            let editor = self.empty_editor();
+            self.add(editor);
+        }
+        let i = self.tabs.iter().position(|tab| tab.id == id).unwrap();
+        if self.panes.contains(id) {
+            self.show(id);
+            if self.panes.ids().len() > 1 {
+                self.close_pane();
+            } else {
+                let next = if i + 1 < self.tabs.len() {
+                    i + 1
+                } else {
+                    i - 1
+                };
+                self.panes.replace(id, self.tabs[next].id);
+                self.active = next;
+            }
+        }
+        let focused = self.active_id();
+        self.tabs.remove(i);
+        self.active = self.tabs.iter().position(|tab| tab.id == focused).unwrap();
+        self.layout_panes();
+    }
+
+    /// Pastes clipboard text into the focused pane, see `Editor::poll`.
+    pub fn poll(&mut self) -> bool {
+        self.editor_mut().poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Keymap, Settings};
+
+    fn workspace(texts: &[&str]) -> Workspace {
+        let editor = |text| Editor::new(text, Settings::default(), Keymap::default());
+        let mut workspace = Workspace::new(editor(texts[0]));
+        for text in &texts[1..] {
+            workspace.open(editor(text));
+        }
+        workspace.show(0);
+        workspace
+    }
+
+    fn play(workspace: &mut Workspace, script: &str) -> Vec<(usize, Effect)> {
+        for event in crate::key_events(script).unwrap() {
+            workspace.input(&event);
+        }
+        workspace.take_effects()
+    }
+
+    #[test]
+    fn buffers_keep_their_own_cursors_and_history() {
+        let mut workspace = workspace(&["a", "b"]);
+        play(&mut workspace, "<ctrl+end>1<ctrl+tab><ctrl+end>2<ctrl+tab>");
+        assert_eq!(workspace.editor().text(), "a1");
+        play(&mut workspace, "<ctrl+z><ctrl+shift+tab>");
+        assert_eq!(workspace.editor().text(), "b2");
+        assert_eq!(workspace.editor().cursors(), vec![2]);
+        play(&mut workspace, "<ctrl+tab>");
+        assert_eq!(workspace.editor().text(), "a");
+    }
+
+    #[test]
+    fn buffers_share_the_clipboard() {
+        let mut workspace = workspace(&["sin 440", "b"]);
+        play(
+            &mut workspace,
+            "<shift+end><ctrl+c><ctrl+tab><ctrl+end><ctrl+v>",
+        );
+        assert_eq!(workspace.editor().text(), "bsin 440");
+    }
+
+    #[test]
+    fn closing_a_buffer_with_unsaved_changes_needs_a_second_request() {
+        let mut workspace = workspace(&["a", "b"]);
+        play(&mut workspace, "x");
+        assert!(play(&mut workspace, "<ctrl+w>").is_empty());
+        assert_eq!(
+            play(&mut workspace, "<ctrl+w>"),
+            vec![(0, Effect::CloseBuffer)]
+        );
+        assert_eq!(workspace.editor().text(), "b");
+        // the last buffer leaves an empty one behind
+        play(&mut workspace, "<ctrl+w>");
+        assert_eq!(workspace.editor().text(), "");
+        assert_eq!(workspace.editors().count(), 1);
+    }
+
+    #[test]
+    fn closing_the_window_shows_each_unsaved_buffer() {
+        let mut workspace = workspace(&["a", "b", "c"]);
+        play(&mut workspace, "<ctrl+tab>x<ctrl+tab>y<ctrl+tab>");
+        assert!(!workspace.request_close());
+        assert_eq!(workspace.active_id(), 1);
+        assert!(!workspace.request_close());
+        assert_eq!(workspace.active_id(), 2);
+        assert!(workspace.request_close());
+    }
+
+    #[test]
+    fn evaluations_say_which_buffer_they_come_from() {
+        let mut workspace = workspace(&["a", "b"]);
+        play(&mut workspace, "<ctrl+n>");
+        assert_eq!(workspace.active_id(), 2);
+        let effects = play(&mut workspace, "<ctrl+e><shift+enter>");
+        assert_eq!(effects, vec![(2, Effect::Evaluate(String::new()))]);
+        assert!(workspace.editor().own_engine());
+        let effects = play(&mut workspace, "<ctrl+e>");
+        assert_eq!(effects, vec![(2, Effect::StopEngine)]);
+        assert!(!workspace.editor().own_engine());
+    }
+
+    #[test]
+    fn panes_show_buffers_side_by_side() {
+        let mut workspace = workspace(&["a", "b", "c"]);
+        play(&mut workspace, "<ctrl+\\>");
+        assert_eq!(workspace.shown(), vec![0, 1]);
+        assert_eq!(workspace.active_id(), 1);
+        play(&mut workspace, "<ctrl+shift+\\>");
+        assert_eq!(workspace.shown(), vec![0, 1, 2]);
+        // every buffer is shown, so the next split opens an empty one
+        play(&mut workspace, "<ctrl+\\>");
+        assert_eq!(workspace.shown(), vec![0, 1, 2, 3]);
+        assert_eq!(workspace.editor().text(), "");
+        play(&mut workspace, "<ctrl+shift+w>");
+        assert_eq!(workspace.shown(), vec![0, 1, 2]);
+        assert_eq!(workspace.active_id(), 2);
+        assert_eq!(workspace.editors().count(), 4);
+    }
+
+    #[test]
+    fn focus_moves_between_panes() {
+        let mut workspace = workspace(&["a", "b", "c"]);
+        workspace.resize(PhysicalSize::new(800, 600), 1.0);
+        // a | b over c
+        play(&mut workspace, "<ctrl+\\><ctrl+shift+\\>");
+        assert_eq!(workspace.active_id(), 2);
+        play(&mut workspace, "<alt+up>1<alt+left>2<alt+left><alt+right>");
+        assert_eq!(workspace.active_id(), 1);
+        play(&mut workspace, "<alt+down>3");
+        let texts: Vec<_> = workspace.editors().map(|(_, e)| e.text()).collect();
+        assert_eq!(texts, vec!["2a", "1b", "3c"]);
+        // switching to a buffer in another pane focuses that pane
+        play(&mut workspace, "<ctrl+tab>");
+        assert_eq!(workspace.active_id(), 0);
+        assert_eq!(workspace.shown(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn closing_a_buffer_closes_its_pane() {
+        let mut workspace = workspace(&["a", "b"]);
+        play(&mut workspace, "<ctrl+\\>");
+        let effects = play(&mut workspace, "<ctrl+w>");
+        assert_eq!(effects, vec![(1, Effect::CloseBuffer)]);
+        assert_eq!(workspace.shown(), vec![0]);
+        assert_eq!(workspace.active_id(), 0);
+    }
+}
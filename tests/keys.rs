@@ -0,0 +1,227 @@
+//! Key-script regression tests: `|` marks the cursors before and after.
+
+use glicol_wgpu::{Editor, Keymap, Selection, Settings};
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
+
+fn parse(marked: &str) -> (String, Vec<usize>) {
+    let mut text = String::new();
+    let mut cursors = vec![];
+    for ch in marked.chars() {
+        if ch == '|' {
+            cursors.push(text.chars().count());
+        } else {
+            text.push(ch);
+        }
+    }
+    (text, cursors)
+}
+
+fn mark(editor: &Editor) -> String {
+    let cursors = editor.cursors();
+    let mut marked = String::new();
+    for (i, ch) in editor.text().chars().enumerate() {
+        if cursors.contains(&i) {
+            marked.push('|');
+        }
+        marked.push(ch);
+    }
+    if cursors.contains(&editor.text().chars().count()) {
+        marked.push('|');
+    }
+    marked
+}
+
+fn check_with(settings: Settings, before: &str, script: &str, after: &str) {
+    let (text, cursors) = parse(before);
+    let mut editor = Editor::new(&text, settings, Keymap::default());
+    editor.set_selections(cursors.into_iter().map(Selection::cursor).collect());
+    editor.play(script).unwrap();
+    assert_eq!(mark(&editor), after, "{:?} after {:?}", before, script);
+}
+
+fn check(before: &str, script: &str, after: &str) {
+    check_with(Settings::default(), before, script, after);
+}
+
+#[test]
+fn scripts_reject_unknown_keys() {
+    assert!(glicol_wgpu::key_events("<hyper+q>").is_err());
+    assert!(glicol_wgpu::key_events("<up").is_err());
+    assert_eq!(glicol_wgpu::key_events("a<<b").unwrap().len(), 11);
+}
+
+#[test]
+fn scripts_press_the_key_of_each_char() {
+    let events = glicol_wgpu::key_events("a").unwrap();
+    assert!(matches!(
+        events[..],
+        [
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(VirtualKeyCode::A),
+                    ..
+                },
+                ..
+            },
+            WindowEvent::ReceivedCharacter('a'),
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Released,
+                    virtual_keycode: Some(VirtualKeyCode::A),
+                    ..
+                },
+                ..
+            },
+        ]
+    ));
+    // shift is held for capitals, chars without a key arrive alone
+    assert_eq!(glicol_wgpu::key_events("A").unwrap().len(), 5);
+    assert_eq!(glicol_wgpu::key_events("ö").unwrap().len(), 1);
+    check("|", "Saw 440 >> mul(0.5); ö", "Saw 440 >> mul(0.5); ö|");
+}
+
+#[test]
+fn up_from_the_second_line_under_an_empty_first_line() {
+    check("\n|abc", "<up>", "|\nabc");
+    check("\nab|c", "<up>", "|\nabc");
+    check("\n\n|abc", "<up><up>", "|\n\nabc");
+    check("|\nabc", "<up>", "|\nabc");
+}
+
+#[test]
+fn down_to_an_empty_last_line() {
+    check("ab|c\n", "<down>", "abc\n|");
+    check("abc\n|", "<down>", "abc\n|");
+}
+
+#[test]
+fn up_and_down_keep_the_column() {
+    check("abcd|\nx\nefgh", "<down>", "abcd\nx|\nefgh");
+    check("abcd\nef|gh", "<up>", "ab|cd\nefgh");
+    check("a|bcd\nx\nefgh", "<down><down>", "abcd\nx\ne|fgh");
+}
+
+#[test]
+fn up_stays_on_the_first_line_and_down_ends_the_last() {
+    check("ab|c\nd", "<up>", "ab|c\nd");
+    check("abc\n|d", "<down>", "abc\nd|");
+}
+
+#[test]
+fn left_and_right_cross_lines() {
+    check("ab\n|c", "<left>", "ab|\nc");
+    check("ab|\nc", "<right>", "ab\n|c");
+    check("|ab", "<left>", "|ab");
+    check("ab|", "<right>", "ab|");
+}
+
+#[test]
+fn word_and_line_jumps() {
+    check("|~t1: seq 60", "<ctrl+right><ctrl+right>", "~t1:| seq 60");
+    check("~t1: seq 60|", "<ctrl+left>", "~t1: seq |60");
+    check("  ab|c", "<home>", "  |abc");
+    check("  ab|c", "<home><home>", "|  abc");
+    check("|abc\nd", "<end>", "abc|\nd");
+    check("a\nb|\nc", "<ctrl+home>", "|a\nb\nc");
+    check("a\nb|\nc", "<ctrl+end>", "a\nb\nc|");
+}
+
+#[test]
+fn shift_extends_the_selection() {
+    let mut editor = Editor::new("sin 440", Settings::default(), Keymap::default());
+    editor
+        .play("<shift+right><shift+right><shift+right>")
+        .unwrap();
+    assert_eq!(editor.selections(), &[Selection::new(0, 3)]);
+    editor.play("saw").unwrap();
+    assert_eq!(editor.text(), "saw 440");
+    editor.play("<shift+home><shift+end>").unwrap();
+    assert_eq!(editor.selections(), &[Selection::new(3, 7)]);
+}
+
+#[test]
+fn typing_and_new_lines() {
+    check("|", "out: sin 440", "out: sin 440|");
+    check(
+        "~a: sin 440|",
+        "\n>> mul 0.5",
+        "~a: sin 440\n    >> mul 0.5|",
+    );
+    check("~a: sin 440|", "<enter>", "~a: sin 440\n    |");
+    check("a|b", "<enter>", "a\n|b");
+}
+
+#[test]
+fn shift_enter_evaluates_instead_of_typing() {
+    let mut editor = Editor::new("out: sin 440", Settings::default(), Keymap::default());
+    editor.play("<shift+enter>").unwrap();
+    assert_eq!(editor.text(), "out: sin 440");
+    assert_eq!(editor.take_effects().len(), 1);
+}
+
+#[test]
+fn brackets_and_quotes() {
+    check("|", "(", "(|)");
+    check("|", "()", "()|");
+    check("|", "\"", "\"|\"");
+    check("a|", "[", "a[|]");
+    check("sin |", "<<", "sin <|");
+}
+
+#[test]
+fn deletion() {
+    check("ab|c", "<backspace>", "a|c");
+    check("|abc", "<backspace>", "|abc");
+    check("ab|c", "<delete>", "ab|");
+    check("abc|", "<delete>", "abc|");
+    check("a\n|b", "<backspace>", "a|b");
+    check("a|\nb", "<delete>", "a|b");
+    check("(|)", "<backspace>", "|");
+    check("sin 440|", "<ctrl+backspace>", "sin |");
+    check("|sin 440", "<ctrl+delete>", "| 440");
+}
+
+#[test]
+fn deletion_with_a_selection() {
+    check("sin 4|40", "<shift+left><shift+left><backspace>", "sin|40");
+    check("sin 4|40", "<shift+right><delete>", "sin 4|0");
+}
+
+#[test]
+fn multiple_cursors() {
+    check(
+        "|a\nb\nc",
+        "<ctrl+alt+down><ctrl+alt+down>~",
+        "~|a\n~|b\n~|c",
+    );
+    check("a|\nb|", "<backspace>", "|\n|");
+    check("|a\nb", "<ctrl+alt+down><escape>x", "x|a\nb");
+}
+
+#[test]
+fn undo_and_redo() {
+    check("|", "abc<ctrl+z>", "|");
+    check("|", "abc<ctrl+z><ctrl+shift+z>", "abc|");
+    check("ab|c", "<backspace><ctrl+z>", "ab|c");
+}
+
+#[test]
+fn alt_chords_type_nothing() {
+    let mut editor = Editor::new("saw 440", Settings::default(), Keymap::default());
+    editor.play("<alt+z>").unwrap();
+    assert_eq!(editor.text(), "saw 440");
+    assert!(editor.settings().wrap);
+    check("|ab", "<alt+z>x", "x|ab");
+}
+
+#[test]
+fn vim_scripts() {
+    let vim = || Settings {
+        vim: true,
+        ..Settings::default()
+    };
+    check_with(vim(), "|sin 440", "dw", "|440");
+    check_with(vim(), "|sin 440", "A!<escape>", "sin 440|!");
+    check_with(vim(), "\n|abc", "k", "|\nabc");
+}